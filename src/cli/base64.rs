@@ -1,13 +1,22 @@
 use core::fmt;
-use std::str::FromStr;
+use std::{
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    path::Path,
+    str::FromStr,
+};
 
 use anyhow::Ok;
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 
-use crate::{process_decode, process_encode, CmdExecutor};
+use crate::{
+    get_reader, get_writer, hexdump, is_binary, process_datauri_decode, process_datauri_encode,
+    process_decode, process_decode_lenient, process_encode, process_pack, process_unpack,
+    write_output, CmdExecutor, OutputFormat,
+};
 
-use super::verify_file;
+use super::{verify_file, verify_path};
 
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
@@ -16,47 +25,234 @@ pub enum Base64SubCommand {
     Encode(Base64EncodeOpts),
     #[command(name = "decode", about = "decode a string to base64")]
     Decode(Base64DecodeOpts),
+    #[command(
+        name = "datauri",
+        about = "create a data uri from a file, or extract its payload"
+    )]
+    DataUri(Base64DataUriOpts),
+    #[command(
+        name = "pack",
+        about = "embed a directory of files into one json/yaml bundle"
+    )]
+    Pack(Base64PackOpts),
+    #[command(name = "unpack", about = "restore and verify the files of a bundle")]
+    Unpack(Base64UnpackOpts),
 }
 
+const HEXDUMP_PREVIEW_LEN: usize = 256;
+
 #[derive(Debug, Parser)]
 pub struct Base64EncodeOpts {
     #[arg(short, long, value_parser=verify_file, default_value="-")]
     pub input: String,
-    #[arg(long, value_parser=parse_base64_format, default_value="standard")]
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long, value_parser=parse_base64_encode_format, default_value="standard")]
     pub format: Base64Format,
+    /// wrap lines at N columns, 0 to disable (default: 76 for mime, 64 for pem)
+    #[arg(long)]
+    pub wrap: Option<usize>,
 }
 
 #[derive(Debug, Parser)]
 pub struct Base64DecodeOpts {
     #[arg(short, long, value_parser=verify_file, default_value="-")]
     pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
     #[arg(long, value_parser=parse_base64_format, default_value="standard")]
     pub format: Base64Format,
+    /// require the decoded data to be valid UTF-8 text
+    #[arg(long, default_value_t = false)]
+    pub text: bool,
+    /// accept either alphabet, even mixed, and missing or extra padding
+    #[arg(long, default_value_t = false)]
+    pub lenient: bool,
+    /// only check the input, don't output the decoded data
+    #[arg(long, default_value_t = false, conflicts_with_all = ["output", "text"])]
+    pub validate: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Base64DataUriOpts {
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    /// stdout by default when encoding, "payload.<ext>" when decoding. The
+    /// extension is added when decoding to a name without one.
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// mime type to use instead of sniffing it
+    #[arg(long, conflicts_with = "decode")]
+    pub mime: Option<String>,
+    /// extract the payload of a data uri
+    #[arg(long, default_value_t = false)]
+    pub decode: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct Base64PackOpts {
+    #[arg(value_parser=verify_path)]
+    pub dir: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long, value_parser=parse_output_format, default_value="json")]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Parser)]
+pub struct Base64UnpackOpts {
+    #[arg(value_parser=verify_file)]
+    pub input: String,
+    /// directory to restore the files into, created if missing
+    #[arg(short, long, default_value = ".")]
+    pub dir: String,
+    /// replace files that already exist
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Base64Format {
     Standard,
+    StandardNoPad,
     UrlSafe,
+    UrlSafePad,
+    /// standard alphabet wrapped at 76 columns with CRLF (RFC 2045)
+    Mime,
+    /// standard alphabet wrapped at 64 columns (RFC 7468)
+    Pem,
+    /// decode only: detect the alphabet and padding from the input
+    Auto,
+}
+
+impl Base64Format {
+    /// the line ending `--wrap` uses, CRLF for MIME
+    pub fn line_ending(self) -> &'static str {
+        match self {
+            Base64Format::Mime => "\r\n",
+            _ => "\n",
+        }
+    }
 }
 
 fn parse_base64_format(format: &str) -> anyhow::Result<Base64Format, anyhow::Error> {
     format.parse()
 }
 
+fn parse_output_format(format: &str) -> anyhow::Result<OutputFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_base64_encode_format(format: &str) -> anyhow::Result<Base64Format, anyhow::Error> {
+    match format.parse()? {
+        Base64Format::Auto => Err(anyhow::anyhow!("auto is only supported for decoding")),
+        format => Ok(format),
+    }
+}
+
+impl Base64DecodeOpts {
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> anyhow::Result<u64> {
+        if self.lenient {
+            process_decode_lenient(reader, writer)
+        } else {
+            process_decode(reader, writer, self.format)
+        }
+    }
+}
+
 impl CmdExecutor for Base64DecodeOpts {
     async fn execute(&self) -> anyhow::Result<()> {
-        let decoded = process_decode(&self.input, self.format)?;
-        //TODO: decoded data might not be string (but for this example, we assume it is)
-        println!("{:?}", String::from_utf8(decoded));
-        Ok(())
+        let mut reader = get_reader(&self.input)?;
+        if self.validate {
+            let n = self.decode(&mut reader, &mut io::sink())?;
+            println!("valid base64, {} bytes", n);
+            return Ok(());
+        }
+
+        // a failed decode leaves no partial output behind
+        let to_tty = self.output == "-" && io::stdout().is_terminal();
+        if !self.text && !to_tty {
+            return write_output(&self.output, |writer| {
+                self.decode(&mut reader, writer)?;
+                Ok(())
+            });
+        }
+
+        // we need to look at the whole data before writing it
+        let mut decoded = Vec::new();
+        self.decode(&mut reader, &mut decoded)?;
+        if self.text {
+            if let Err(e) = std::str::from_utf8(&decoded) {
+                anyhow::bail!("Decoded data is not valid UTF-8 text: {}", e);
+            }
+        } else if is_binary(&decoded) {
+            // don't mess up the terminal with raw bytes
+            eprintln!(
+                "{} bytes of binary data, use -o to write them to a file",
+                decoded.len()
+            );
+            print!("{}", hexdump(&decoded, HEXDUMP_PREVIEW_LEN));
+            return Ok(());
+        }
+        write_output(&self.output, |writer| Ok(writer.write_all(&decoded)?))
     }
 }
 
 impl CmdExecutor for Base64EncodeOpts {
     async fn execute(&self) -> anyhow::Result<()> {
-        let encoded = process_encode(&self.input, self.format)?;
-        println!("{}", encoded);
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        process_encode(&mut reader, &mut writer, self.format, self.wrap)?;
+        // end the line for the terminal, files get exactly the encoded data
+        if self.output == "-" {
+            write!(writer, "{}", self.format.line_ending())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for Base64DataUriOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        if self.decode {
+            let mut reader = get_reader(&self.input)?;
+            let uri = process_datauri_decode(&mut reader)?;
+            let name = self.output.as_deref().unwrap_or("payload");
+            let name = if Path::new(name).extension().is_some() {
+                name.to_string()
+            } else {
+                format!("{}.{}", name, uri.extension)
+            };
+            fs::write(&name, &uri.payload)?;
+            eprintln!("{} ({}, {} bytes)", name, uri.mime, uri.payload.len());
+        } else {
+            let mut writer = get_writer(self.output.as_deref().unwrap_or("-"))?;
+            process_datauri_encode(&self.input, &mut writer, self.mime.as_deref())?;
+            if self.output.is_none() {
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for Base64PackOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let bundle = process_pack(&self.dir, self.format)?;
+        let mut writer = get_writer(&self.output)?;
+        writer.write_all(bundle.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for Base64UnpackOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let restored = process_unpack(&self.input, &self.dir, self.force)?;
+        for path in restored {
+            eprintln!("{}", path.display());
+        }
         Ok(())
     }
 }
@@ -67,7 +263,12 @@ impl FromStr for Base64Format {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "standard" => Ok(Base64Format::Standard),
+            "standard-nopad" => Ok(Base64Format::StandardNoPad),
             "urlsafe" => Ok(Base64Format::UrlSafe),
+            "urlsafe-pad" => Ok(Base64Format::UrlSafePad),
+            "mime" => Ok(Base64Format::Mime),
+            "pem" => Ok(Base64Format::Pem),
+            "auto" => Ok(Base64Format::Auto),
             _ => Err(anyhow::anyhow!("Invalid base64 format")),
         }
     }
@@ -77,7 +278,12 @@ impl From<Base64Format> for &'static str {
     fn from(format: Base64Format) -> Self {
         match format {
             Base64Format::Standard => "standard",
+            Base64Format::StandardNoPad => "standard-nopad",
             Base64Format::UrlSafe => "urlsafe",
+            Base64Format::UrlSafePad => "urlsafe-pad",
+            Base64Format::Mime => "mime",
+            Base64Format::Pem => "pem",
+            Base64Format::Auto => "auto",
         }
     }
 }
@@ -0,0 +1,109 @@
+use core::fmt;
+use std::{io::Write, str::FromStr};
+
+use clap::Parser;
+
+use crate::{
+    get_reader, get_writer, process_codec_decode, process_codec_encode, write_output, CmdExecutor,
+};
+
+use super::verify_file;
+
+#[derive(Debug, Parser)]
+pub struct EncodeOpts {
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long, value_parser=parse_codec_format, default_value="base64")]
+    pub codec: CodecFormat,
+}
+
+#[derive(Debug, Parser)]
+pub struct DecodeOpts {
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[arg(long, value_parser=parse_codec_format, default_value="base64")]
+    pub codec: CodecFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecFormat {
+    Base64,
+    Base32,
+    Base58,
+    /// ZeroMQ Z85
+    Base85,
+    Ascii85,
+    Hex,
+    /// percent-encoding
+    Url,
+}
+
+fn parse_codec_format(format: &str) -> anyhow::Result<CodecFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl CmdExecutor for EncodeOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let mut writer = get_writer(&self.output)?;
+        process_codec_encode(&mut reader, &mut writer, self.codec)?;
+        // files get exactly the encoded data
+        if self.output == "-" {
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for DecodeOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        // a failed decode leaves no partial output behind
+        write_output(&self.output, |writer| {
+            process_codec_decode(&mut reader, writer, self.codec)?;
+            Ok(())
+        })
+    }
+}
+
+impl FromStr for CodecFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64" => Ok(CodecFormat::Base64),
+            "base32" => Ok(CodecFormat::Base32),
+            "base58" => Ok(CodecFormat::Base58),
+            "base85" | "z85" => Ok(CodecFormat::Base85),
+            "ascii85" => Ok(CodecFormat::Ascii85),
+            "hex" => Ok(CodecFormat::Hex),
+            "url" => Ok(CodecFormat::Url),
+            _ => Err(anyhow::anyhow!("Invalid codec")),
+        }
+    }
+}
+
+impl From<CodecFormat> for &'static str {
+    fn from(format: CodecFormat) -> Self {
+        match format {
+            CodecFormat::Base64 => "base64",
+            CodecFormat::Base32 => "base32",
+            CodecFormat::Base58 => "base58",
+            CodecFormat::Base85 => "base85",
+            CodecFormat::Ascii85 => "ascii85",
+            CodecFormat::Hex => "hex",
+            CodecFormat::Url => "url",
+        }
+    }
+}
+
+impl fmt::Display for CodecFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -1,11 +1,28 @@
-use std::{fmt, str::FromStr};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{self, Write},
+    path::{Component, Path},
+    str::FromStr,
+};
 
 use anyhow::Ok;
 use clap::Parser;
+use enum_dispatch::enum_dispatch;
 
-use crate::{process_csv, CmdExecutor};
+use crate::{process_csv, process_csv_render, CmdExecutor};
 
-use super::verify_file;
+use super::{verify_file, verify_path};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(
+        name = "render",
+        about = "Render a handlebars template for each CSV row"
+    )]
+    Render(CsvRenderOpts),
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
@@ -13,10 +30,14 @@ pub enum OutputFormat {
     Yaml,
 }
 
+/// Converts to JSON or YAML without a subcommand
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct CsvOpts {
-    #[arg(short,long,value_parser=verify_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+    #[arg(short, long, value_parser=verify_file, required = true)]
+    pub input: Option<String>,
     #[arg(short, long)] // "output.json".into()
     pub output: Option<String>,
     #[arg(long, value_parser=parse_format, default_value="json")]
@@ -27,22 +48,83 @@ pub struct CsvOpts {
     pub header: bool,
 }
 
+#[derive(Debug, Parser)]
+pub struct CsvRenderOpts {
+    #[arg(short, long, value_parser=verify_file)]
+    pub input: String,
+    #[arg(short, long, value_parser=verify_file)]
+    pub template: String,
+    /// write one file per row into this directory instead of one combined document
+    #[arg(long, value_parser=verify_path, requires = "name")]
+    pub out_dir: Option<String>,
+    /// file name template for each row, e.g. "{{Name}}.txt"
+    #[arg(long, requires = "out_dir")]
+    pub name: Option<String>,
+    /// combined document, "-" for stdout
+    #[arg(short, long, default_value = "-", conflicts_with = "out_dir")]
+    pub output: String,
+}
+
 fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.cmd {
+            return cmd.execute().await;
+        }
+        let input = self.input.as_deref().unwrap_or_default();
         let output = if let Some(output) = &self.output {
             output.clone()
         } else {
             format!("output.{}", self.format)
         };
-        process_csv(&self.input, output, self.format)?;
+        process_csv(input, output, self.format)?;
         Ok(())
     }
 }
 
+impl CmdExecutor for CsvRenderOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let rows = process_csv_render(&self.input, &self.template, self.name.as_deref())?;
+        if let Some(dir) = &self.out_dir {
+            // validate every name before writing anything
+            let mut names = HashSet::with_capacity(rows.len());
+            for row in &rows {
+                let name = row.name.as_deref().unwrap_or_default();
+                if !is_plain_file_name(name) {
+                    anyhow::bail!("Invalid rendered file name {:?}", name);
+                }
+                if !names.insert(name) {
+                    anyhow::bail!("Rendered file name {:?} is not unique", name);
+                }
+            }
+            for row in &rows {
+                let name = row.name.as_deref().unwrap_or_default();
+                fs::write(Path::new(dir).join(name), &row.content)?;
+            }
+        } else {
+            let content: String = rows.into_iter().map(|row| row.content).collect();
+            if self.output == "-" {
+                io::stdout().write_all(content.as_bytes())?;
+            } else {
+                fs::write(&self.output, content)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// rendered names come from data, keep them inside the output directory
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
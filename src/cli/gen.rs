@@ -0,0 +1,116 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::{value_parser, Parser};
+use enum_dispatch::enum_dispatch;
+
+use crate::{process_gen_token, process_gen_ulid, process_gen_uuid, CmdExecutor};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenSubCommand {
+    #[command(about = "Generate random API tokens")]
+    Token(GenTokenOpts),
+    #[command(about = "Generate v4 or v7 UUIDs")]
+    Uuid(GenUuidOpts),
+    #[command(about = "Generate ULIDs")]
+    Ulid(GenUlidOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenTokenOpts {
+    /// random bytes per token
+    #[arg(long, default_value_t = 32, value_parser = value_parser!(u16).range(1..))]
+    pub bytes: u16,
+    /// base64url, hex or base58
+    #[arg(long, default_value = "base64url", value_parser = parse_token_encoding)]
+    pub encoding: TokenEncoding,
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = value_parser!(u32).range(1..=100_000))]
+    pub count: u32,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenUuidOpts {
+    /// random UUIDs, the default
+    #[arg(long = "v4", default_value_t = false)]
+    pub v4: bool,
+    /// time ordered UUIDs
+    #[arg(long = "v7", default_value_t = false, conflicts_with = "v4")]
+    pub v7: bool,
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = value_parser!(u32).range(1..=100_000))]
+    pub count: u32,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenUlidOpts {
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = value_parser!(u32).range(1..=100_000))]
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenEncoding {
+    Base64Url,
+    Hex,
+    Base58,
+}
+
+impl CmdExecutor for GenTokenOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let tokens = process_gen_token(self.bytes as usize, self.encoding, self.count as usize)?;
+        for token in tokens {
+            println!("{}", token);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenUuidOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        for id in process_gen_uuid(self.v7, self.count as usize) {
+            println!("{}", id);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenUlidOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        for id in process_gen_ulid(self.count as usize) {
+            println!("{}", id);
+        }
+        Ok(())
+    }
+}
+
+fn parse_token_encoding(encoding: &str) -> Result<TokenEncoding, anyhow::Error> {
+    encoding.parse()
+}
+
+impl FromStr for TokenEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64url" => Ok(TokenEncoding::Base64Url),
+            "hex" => Ok(TokenEncoding::Hex),
+            "base58" => Ok(TokenEncoding::Base58),
+            v => Err(anyhow::anyhow!("Unsupported token encoding {}", v)),
+        }
+    }
+}
+
+impl From<TokenEncoding> for &'static str {
+    fn from(encoding: TokenEncoding) -> Self {
+        match encoding {
+            TokenEncoding::Base64Url => "base64url",
+            TokenEncoding::Hex => "hex",
+            TokenEncoding::Base58 => "base58",
+        }
+    }
+}
+
+impl fmt::Display for TokenEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
@@ -1,35 +1,291 @@
+use core::fmt;
+use std::{io::Read, str::FromStr};
+
 use anyhow::Ok;
-use clap::Parser;
+use clap::{value_parser, ArgAction, Parser};
+use enum_dispatch::enum_dispatch;
+
+use crate::{
+    gen_pass_entropy, get_reader, load_policy, load_wordlist, process_gen_pass,
+    process_gen_pass_bulk, process_gen_pass_policy, process_gen_passphrase, process_gen_pattern,
+    process_pass_audit, render_pass_records, CmdExecutor, GenPassConfig, OutputFormat,
+};
 
-use crate::CmdExecutor;
-use zxcvbn::zxcvbn;
+use super::verify_file;
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
-    #[arg(short, long, default_value_t = 16)]
-    pub length: u8,
-    #[arg(long, default_value_t = true)]
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+    /// password length [default: 16, or the closest length the policy allows]
+    #[arg(short, long, value_parser = value_parser!(u8).range(1..))]
+    pub length: Option<u8>,
+    /// leave out uppercase chars
+    #[arg(long = "no-uppercase", action = ArgAction::SetFalse)]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// leave out lowercase chars
+    #[arg(long = "no-lowercase", action = ArgAction::SetFalse)]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// leave out number chars
+    #[arg(long = "no-number", action = ArgAction::SetFalse)]
     pub number: bool,
-    #[arg(long, default_value_t = true)]
+    /// leave out symbol chars
+    #[arg(long = "no-symbol", action = ArgAction::SetFalse)]
     pub symbol: bool,
+    /// at least N uppercase chars
+    #[arg(long, default_value_t = 0)]
+    pub min_upper: u8,
+    /// at least N number chars
+    #[arg(long, default_value_t = 0)]
+    pub min_digits: u8,
+    /// at least N symbol chars
+    #[arg(long, default_value_t = 0)]
+    pub min_symbols: u8,
+    /// allowed chars, e.g. 'a-zA-Z0-9', replaces the built-in classes
+    #[arg(long, conflicts_with = "pronounceable")]
+    pub charset: Option<String>,
+    /// symbol chars to use as is, e.g. '!@#-_'
+    #[arg(long)]
+    pub symbols: Option<String>,
+    /// chars to leave out, e.g. 'oO0'
+    #[arg(long, default_value = "")]
+    pub exclude: String,
+    /// leave the lookalikes (I, O, l, 0) out of --charset and --symbols too
+    #[arg(long, default_value_t = false)]
+    pub no_ambiguous: bool,
+    /// add the lookalikes (I, O, l, 0) to the built-in classes, which leave them out
+    #[arg(long, default_value_t = false, conflicts_with = "no_ambiguous")]
+    pub lookalikes: bool,
+    /// no char is the same as the one before it
+    #[arg(long, default_value_t = false)]
+    pub no_repeat: bool,
+    /// alternate consonants and vowels, with digits and symbols at the end
+    #[arg(long, default_value_t = false)]
+    pub pronounceable: bool,
+    /// generate from a pattern instead, e.g. '[A-Z]{3}\d{4}': [..] sets, \d \u \l \a \w \s
+    /// \h \H classes, {3} or {2,5} repeats, anything else is literal
+    #[arg(long, conflicts_with_all = [
+        "words", "policy", "charset", "symbols", "pronounceable", "length", "uppercase",
+        "lowercase", "number", "symbol", "min_upper", "min_digits", "min_symbols", "no_repeat",
+    ])]
+    pub pattern: Option<String>,
+    /// generate N unique passwords, at most 100000
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = value_parser!(u32).range(1..=100_000))]
+    pub count: u32,
+    /// plain, csv, json or yaml, the structured formats add the score and entropy
+    #[arg(long, value_parser = parse_genpass_format, default_value = "plain")]
+    pub format: GenPassFormat,
+    /// YAML or TOML file of named password policies to satisfy
+    #[arg(long, value_parser = verify_file, conflicts_with = "words")]
+    pub policy: Option<String>,
+    /// the policy to use, may be left out if the file has only one
+    #[arg(long, requires = "policy")]
+    pub profile: Option<String>,
+    /// generate a passphrase of N random words instead
+    #[arg(long, conflicts_with_all = [
+        "policy", "charset", "symbols", "pronounceable", "length", "uppercase", "lowercase",
+        "number", "symbol", "min_upper", "min_digits", "min_symbols", "no_repeat", "exclude",
+        "no_ambiguous", "lookalikes",
+    ])]
+    pub words: Option<u8>,
+    #[arg(long, default_value = "-", requires = "words")]
+    pub separator: String,
+    #[arg(long, default_value_t = false, requires = "words")]
+    pub capitalize: bool,
+    /// append a random digit to one of the words
+    #[arg(long, default_value_t = false, requires = "words")]
+    pub add_number: bool,
+    /// one word per line, replaces the embedded wordlist
+    #[arg(long, value_parser=verify_file, requires = "words")]
+    pub wordlist: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenPassFormat {
+    Plain,
+    Csv,
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenPassSubCommand {
+    #[command(about = "Check a password from stdin against a password policy")]
+    Check(GenPassCheckOpts),
+    #[command(about = "Estimate the strength of passwords, one per line, without echoing them")]
+    Audit(GenPassAuditOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    /// YAML or TOML file of named password policies
+    #[arg(long, value_parser = verify_file)]
+    pub policy: String,
+    /// the policy to use, may be left out if the file has only one
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// file whose first line is the password
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct GenPassAuditOpts {
+    /// file with one password per line
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    /// a word the passwords shouldn't be built from, such as a username
+    #[arg(short, long = "user-input")]
+    pub user_inputs: Vec<String>,
+    /// json or yaml report instead of text
+    #[arg(long, value_parser = parse_output_format)]
+    pub format: Option<OutputFormat>,
 }
 
 impl CmdExecutor for GenPassOpts {
     async fn execute(&self) -> anyhow::Result<()> {
-        let ret = crate::process_gen_pass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        println!("{}", ret);
-        let estimate = zxcvbn(&ret, &[])?;
-        eprint!("password strength: {}", estimate.score());
+        if let Some(cmd) = &self.cmd {
+            return cmd.execute().await;
+        }
+        let records = if let Some(pattern) = &self.pattern {
+            process_gen_pass_bulk(self.count as usize, || {
+                process_gen_pattern(pattern, &self.exclude, self.no_ambiguous, self.lookalikes)
+            })?
+        } else if let Some(words) = self.words {
+            let list = load_wordlist(self.wordlist.as_deref())?;
+            process_gen_pass_bulk(self.count as usize, || {
+                process_gen_passphrase(
+                    words,
+                    &self.separator,
+                    self.capitalize,
+                    self.add_number,
+                    &list,
+                )
+            })?
+        } else {
+            let policy = match &self.policy {
+                Some(path) => Some(load_policy(path, self.profile.as_deref())?),
+                None => None,
+            };
+            let length = self
+                .length
+                .or(policy.as_ref().map(|p| p.default_length()))
+                .unwrap_or(16);
+            let config = GenPassConfig {
+                length,
+                upper: self.uppercase,
+                lower: self.lowercase,
+                number: self.number,
+                symbol: self.symbol,
+                min_upper: self.min_upper,
+                min_digits: self.min_digits,
+                min_symbols: self.min_symbols,
+                charset: self.charset.clone(),
+                symbols: self.symbols.clone(),
+                exclude: self.exclude.clone(),
+                no_ambiguous: self.no_ambiguous,
+                lookalikes: self.lookalikes,
+                no_repeat: self.no_repeat,
+                pronounceable: self.pronounceable,
+            };
+            let entropy = match &policy {
+                Some(policy) => gen_pass_entropy(&policy.apply(&config))?,
+                None => gen_pass_entropy(&config)?,
+            };
+            process_gen_pass_bulk(self.count as usize, || {
+                let password = match &policy {
+                    Some(policy) => process_gen_pass_policy(&config, policy)?,
+                    None => process_gen_pass(&config)?,
+                };
+                Ok((password, entropy))
+            })?
+        };
+
+        match (records.as_slice(), self.format) {
+            // a single password keeps its strength out of the way on stderr
+            ([record], GenPassFormat::Plain) => {
+                println!("{}", record.password);
+                eprintln!(
+                    "password strength: {}, entropy: {:.1} bits",
+                    record.score, record.entropy_bits
+                );
+            }
+            _ => print!("{}", render_pass_records(&records, self.format)?),
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenPassCheckOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let policy = load_policy(&self.policy, self.profile.as_deref())?;
+        let mut reader = get_reader(&self.input)?;
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf)?;
+        let password = buf.lines().next().unwrap_or_default();
+        let violations = policy.check(password)?;
+        if !violations.is_empty() {
+            for violation in &violations {
+                println!("- the password {}", violation);
+            }
+            anyhow::bail!("The password breaks {} policy rules", violations.len());
+        }
+        println!("The password satisfies the policy");
         Ok(())
     }
 }
+
+impl CmdExecutor for GenPassAuditOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        let user_inputs = self
+            .user_inputs
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let ret = process_pass_audit(&mut reader, &user_inputs, self.format)?;
+        print!("{}", ret);
+        Ok(())
+    }
+}
+
+fn parse_output_format(format: &str) -> anyhow::Result<OutputFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_genpass_format(format: &str) -> anyhow::Result<GenPassFormat, anyhow::Error> {
+    format.parse()
+}
+
+impl From<GenPassFormat> for &'static str {
+    fn from(format: GenPassFormat) -> Self {
+        match format {
+            GenPassFormat::Plain => "plain",
+            GenPassFormat::Csv => "csv",
+            GenPassFormat::Json => "json",
+            GenPassFormat::Yaml => "yaml",
+        }
+    }
+}
+
+impl FromStr for GenPassFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(GenPassFormat::Plain),
+            "csv" => Ok(GenPassFormat::Csv),
+            "json" => Ok(GenPassFormat::Json),
+            "yaml" => Ok(GenPassFormat::Yaml),
+            v => Err(anyhow::anyhow!("Unsupported format {}", v)),
+        }
+    }
+}
+
+impl fmt::Display for GenPassFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
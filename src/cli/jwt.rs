@@ -0,0 +1,131 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::Parser;
+use enum_dispatch::enum_dispatch;
+
+use crate::{process_jwt_decode, process_jwt_sign, process_jwt_verify, CmdExecutor, JwtValidation};
+
+use super::verify_file;
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum JwtSubCommand {
+    #[command(about = "Show the header and claims of a JWT without verifying it")]
+    Decode(JwtDecodeOpts),
+    #[command(about = "Sign json claims as a JWT")]
+    Sign(JwtSignOpts),
+    #[command(about = "Verify the signature and claims of a JWT")]
+    Verify(JwtVerifyOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtDecodeOpts {
+    /// file containing the token
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtSignOpts {
+    /// file containing the json claims
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    /// Ed25519 private key for EdDSA, shared secret for HS256
+    #[arg(short, long, value_parser=verify_file)]
+    pub key: String,
+    #[arg(long, default_value="EdDSA", value_parser=parse_jwt_alg)]
+    pub alg: JwtAlg,
+    /// set `iat` to now and `exp` to now + N seconds
+    #[arg(long)]
+    pub exp: Option<u64>,
+}
+
+#[derive(Debug, Parser)]
+pub struct JwtVerifyOpts {
+    /// file containing the token
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    /// Ed25519 public key for EdDSA, shared secret for HS256
+    #[arg(short, long, value_parser=verify_file)]
+    pub key: String,
+    #[arg(long, default_value="EdDSA", value_parser=parse_jwt_alg)]
+    pub alg: JwtAlg,
+    /// seconds of clock skew tolerated for exp, nbf and iat
+    #[arg(long, default_value_t = 60)]
+    pub leeway: u64,
+    /// require this audience in the aud claim
+    #[arg(long)]
+    pub aud: Option<String>,
+    /// require this issuer in the iss claim
+    #[arg(long)]
+    pub iss: Option<String>,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwtAlg {
+    EdDSA,
+    HS256,
+}
+
+fn parse_jwt_alg(alg: &str) -> anyhow::Result<JwtAlg, anyhow::Error> {
+    alg.parse()
+}
+
+impl CmdExecutor for JwtDecodeOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let decoded = process_jwt_decode(&self.input)?;
+        print!("{}", decoded);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for JwtSignOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let token = process_jwt_sign(&self.input, &self.key, self.alg, self.exp)?;
+        println!("{}", token);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for JwtVerifyOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let validation = JwtValidation {
+            leeway: self.leeway,
+            audience: self.aud.clone(),
+            issuer: self.iss.clone(),
+        };
+        let claims = process_jwt_verify(&self.input, &self.key, self.alg, &validation)?;
+        eprintln!("Token is valid");
+        println!("{}", serde_json::to_string_pretty(&claims)?);
+        Ok(())
+    }
+}
+
+impl FromStr for JwtAlg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "eddsa" => Ok(JwtAlg::EdDSA),
+            "hs256" => Ok(JwtAlg::HS256),
+            v => Err(anyhow::anyhow!("Unsupported JWT algorithm {}", v)),
+        }
+    }
+}
+
+impl From<JwtAlg> for &'static str {
+    fn from(alg: JwtAlg) -> Self {
+        match alg {
+            JwtAlg::EdDSA => "EdDSA",
+            JwtAlg::HS256 => "HS256",
+        }
+    }
+}
+
+impl fmt::Display for JwtAlg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
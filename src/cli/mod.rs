@@ -1,14 +1,18 @@
 mod base64;
+mod codec;
 mod csv;
+mod gen;
 mod genpass;
 mod http;
+mod jwt;
+mod otp;
 mod text;
 
 use clap::Parser;
 use enum_dispatch::enum_dispatch;
 use std::path::Path;
 
-pub use self::{base64::*, csv::*, genpass::*, http::*, text::*};
+pub use self::{base64::*, codec::*, csv::*, gen::*, genpass::*, http::*, jwt::*, otp::*, text::*};
 
 #[derive(Debug, Parser)]
 #[command(name="cli",version,author,about,long_about=None)]
@@ -20,16 +24,32 @@ pub struct Opts {
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
 pub enum SubCommand {
-    #[command(name = "csv", about = "Show CSV, or Convert CSV to JSON")]
+    #[command(
+        name = "csv",
+        about = "Convert CSV to JSON, or render templates from CSV rows"
+    )]
     Csv(CsvOpts),
     #[command(name = "genpass", about = "Generate a random password")]
     GenPass(GenPassOpts),
+    #[command(subcommand, about = "Generate random tokens, UUIDs and ULIDs")]
+    Gen(GenSubCommand),
     #[command(subcommand)]
     Base64(Base64SubCommand),
+    #[command(
+        name = "encode",
+        about = "Encode binary data as text with the given codec"
+    )]
+    Encode(EncodeOpts),
+    #[command(name = "decode", about = "Decode text with the given codec")]
+    Decode(DecodeOpts),
     #[command(subcommand)]
     Text(TextSubCommand),
     #[command(subcommand)]
     Http(HttpSubCommand),
+    #[command(subcommand)]
+    Jwt(JwtSubCommand),
+    #[command(subcommand, about = "Generate and verify TOTP/HOTP one-time passwords")]
+    Otp(OtpSubCommand),
 }
 
 fn verify_file(file: &str) -> Result<String, String> {
@@ -0,0 +1,174 @@
+use core::fmt;
+use std::str::FromStr;
+
+use clap::{value_parser, Parser};
+use enum_dispatch::enum_dispatch;
+
+use crate::{
+    decode_otp_secret, otpauth_uri, process_hotp, process_hotp_verify, process_otp_secret,
+    process_totp, process_totp_verify, unix_now, CmdExecutor, OtpConfig,
+};
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum OtpSubCommand {
+    #[command(about = "Generate a random base32 secret and its otpauth:// URI")]
+    Secret(OtpSecretOpts),
+    #[command(about = "Print the current TOTP code, or a HOTP code with --counter")]
+    Code(OtpCodeOpts),
+    #[command(about = "Verify a TOTP code, or a HOTP code with --counter")]
+    Verify(OtpVerifyOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpParams {
+    #[arg(long, default_value = "sha1", value_parser = parse_otp_algorithm)]
+    pub algorithm: OtpAlgorithm,
+    #[arg(long, default_value_t = 6, value_parser = value_parser!(u8).range(6..=8))]
+    pub digits: u8,
+    /// TOTP time step in seconds
+    #[arg(long, default_value_t = 30, value_parser = value_parser!(u64).range(1..))]
+    pub period: u64,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpSecretOpts {
+    /// secret size, 20 bytes matches SHA1
+    #[arg(long, default_value_t = 20, value_parser = value_parser!(u16).range(10..))]
+    pub bytes: u16,
+    /// the account shown in the authenticator app
+    #[arg(long, default_value = "rcli")]
+    pub account: String,
+    #[arg(long)]
+    pub issuer: Option<String>,
+    #[command(flatten)]
+    pub params: OtpParams,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpCodeOpts {
+    /// base32 secret
+    #[arg(short, long)]
+    pub secret: String,
+    /// HOTP counter, TOTP is used without it
+    #[arg(long)]
+    pub counter: Option<u64>,
+    #[command(flatten)]
+    pub params: OtpParams,
+}
+
+#[derive(Debug, Parser)]
+pub struct OtpVerifyOpts {
+    /// base32 secret
+    #[arg(short, long)]
+    pub secret: String,
+    #[arg(short, long)]
+    pub code: String,
+    /// HOTP counter, TOTP is used without it
+    #[arg(long)]
+    pub counter: Option<u64>,
+    /// TOTP steps accepted on each side of now, or HOTP counters accepted ahead, up to 10
+    #[arg(short, long, default_value_t = 1, value_parser = value_parser!(u64).range(0..=10))]
+    pub window: u64,
+    #[command(flatten)]
+    pub params: OtpParams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl From<&OtpParams> for OtpConfig {
+    fn from(params: &OtpParams) -> Self {
+        Self {
+            algorithm: params.algorithm,
+            digits: params.digits,
+            period: params.period,
+        }
+    }
+}
+
+impl CmdExecutor for OtpSecretOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let secret = process_otp_secret(self.bytes as usize);
+        let config = OtpConfig::from(&self.params);
+        println!("{}", secret);
+        println!(
+            "{}",
+            otpauth_uri(&secret, &self.account, self.issuer.as_deref(), &config)
+        );
+        Ok(())
+    }
+}
+
+impl CmdExecutor for OtpCodeOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let key = decode_otp_secret(&self.secret)?;
+        let config = OtpConfig::from(&self.params);
+        let code = match self.counter {
+            Some(counter) => process_hotp(&key, counter, &config)?,
+            None => process_totp(&key, unix_now(), &config)?,
+        };
+        println!("{}", code);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for OtpVerifyOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let key = decode_otp_secret(&self.secret)?;
+        let config = OtpConfig::from(&self.params);
+        match self.counter {
+            Some(counter) => {
+                match process_hotp_verify(&key, &self.code, counter, self.window, &config)? {
+                    Some(matched) => println!("Code is valid, the next counter is {}", matched + 1),
+                    None => anyhow::bail!("Invalid code"),
+                }
+            }
+            None => {
+                match process_totp_verify(&key, &self.code, unix_now(), self.window, &config)? {
+                    Some(0) => println!("Code is valid"),
+                    Some(offset) => println!("Code is valid, {} steps off", offset),
+                    None => anyhow::bail!("Invalid code"),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_otp_algorithm(algorithm: &str) -> Result<OtpAlgorithm, anyhow::Error> {
+    algorithm.parse()
+}
+
+impl FromStr for OtpAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sha1" => Ok(OtpAlgorithm::Sha1),
+            "sha256" => Ok(OtpAlgorithm::Sha256),
+            "sha512" => Ok(OtpAlgorithm::Sha512),
+            v => Err(anyhow::anyhow!("Unsupported OTP algorithm {}", v)),
+        }
+    }
+}
+
+impl From<OtpAlgorithm> for &'static str {
+    fn from(algorithm: OtpAlgorithm) -> Self {
+        match algorithm {
+            OtpAlgorithm::Sha1 => "sha1",
+            OtpAlgorithm::Sha256 => "sha256",
+            OtpAlgorithm::Sha512 => "sha512",
+        }
+    }
+}
+
+impl fmt::Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
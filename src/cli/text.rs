@@ -1,30 +1,63 @@
 use core::fmt;
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{fs, io::Write, path::Path};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use enum_dispatch::enum_dispatch;
 
-use crate::{process_generate, process_text_sign, process_text_verify, CmdExecutor};
+use crate::{
+    get_reader, get_writer, process_generate, process_key_rekey, process_text_decrypt,
+    process_text_encrypt, process_text_sign, process_text_verify, write_file_atomic,
+    write_secret_file, CmdExecutor,
+};
 
 use super::{verify_file, verify_path};
 
 #[derive(Debug, Parser)]
 #[enum_dispatch(CmdExecutor)]
 pub enum TextSubCommand {
-    #[command(about = "Sign a message with a private/shared key")]
-    Verify(TextVerifyOpts),
     #[command(about = "Verify a signed message")]
+    Verify(TextVerifyOpts),
+    #[command(about = "Sign a message with a private/shared key")]
     Sign(TextSignOpts),
     #[command(about = "Generate a new key")]
     Generate(TextKeyGenerateOpts),
+    #[command(about = "Encrypt a file with a shared key or a passphrase")]
+    Encrypt(TextEncryptOpts),
+    #[command(about = "Decrypt a file encrypted by rcli")]
+    Decrypt(TextDecryptOpts),
+    #[command(subcommand, about = "Manage key files")]
+    Key(TextKeySubCommand),
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum TextKeySubCommand {
+    #[command(about = "Change the passphrase of a private key, or encrypt a plain one")]
+    Rekey(TextKeyRekeyOpts),
 }
 
 #[derive(Debug, Parser)]
 pub struct TextKeyGenerateOpts {
-    #[arg(long, default_value="blake3", value_parser=parse_format)]
+    #[arg(long, value_enum, default_value_t = TextSignFormat::Blake3)]
     pub format: TextSignFormat,
-    #[arg(long, value_parser=verify_path)]
-    pub output: PathBuf,
+    /// the ed25519 key file format, loading detects any of them
+    #[arg(long, value_enum, default_value_t = TextKeyFormat::Raw)]
+    pub key_format: TextKeyFormat,
+    /// encrypt the private key with a passphrase from RCLI_PASSPHRASE, the file
+    /// named by RCLI_PASSPHRASE_FILE, or a prompt
+    #[arg(long, default_value_t = false)]
+    pub encrypt: bool,
+    /// directory the key files are written to
+    #[arg(long, value_parser=verify_path, default_value=".")]
+    pub output: String,
+}
+
+/// The current passphrase is read like the one of `generate --encrypt`, the new
+/// one from RCLI_NEW_PASSPHRASE, RCLI_NEW_PASSPHRASE_FILE or a prompt
+#[derive(Debug, Parser)]
+pub struct TextKeyRekeyOpts {
+    #[arg(short, long, value_parser=verify_file)]
+    pub key: String,
 }
 
 #[derive(Debug, Parser)]
@@ -33,7 +66,7 @@ pub struct TextSignOpts {
     pub input: String,
     #[arg(short, long, value_parser=verify_file)]
     pub key: String,
-    #[arg(long, default_value="blacke3", value_parser=parse_format)]
+    #[arg(long, value_enum, default_value_t = TextSignFormat::Blake3)]
     pub format: TextSignFormat,
 }
 
@@ -45,33 +78,62 @@ pub struct TextVerifyOpts {
     pub key: String,
     #[arg(short, long)]
     pub sig: String,
-    #[arg(long, default_value="blacke3", value_parser=parse_format)]
+    #[arg(long, value_enum, default_value_t = TextSignFormat::Blake3)]
     pub format: TextSignFormat,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Without --key a passphrase is read like the one of `generate --encrypt`
+#[derive(Debug, Parser)]
+pub struct TextEncryptOpts {
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// a 32-byte key file, as `generate --format blake3` writes
+    #[arg(short, long, value_parser=verify_file)]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct TextDecryptOpts {
+    #[arg(short, long, value_parser=verify_file, default_value="-")]
+    pub input: String,
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// the key file, not needed for passphrase encrypted input
+    #[arg(short, long, value_parser=verify_file)]
+    pub key: Option<String>,
+}
+
+/// The old misspelled names are still accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum TextSignFormat {
+    #[value(alias = "blacke3")]
     Blake3,
-    Ed2519,
+    #[value(alias = "ed2519")]
+    Ed25519,
+    /// prehashed Ed25519, for large inputs
+    Ed25519ph,
 }
 
-impl FromStr for TextSignFormat {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "blacke3" => Ok(TextSignFormat::Blake3),
-            "ed2519" => Ok(TextSignFormat::Ed2519),
-            _ => Err(anyhow::anyhow!("Invalid format")),
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TextKeyFormat {
+    /// 32 bytes
+    Raw,
+    /// PKCS#8 and SPKI, as openssl writes them
+    Pem,
+    /// as ssh-keygen writes them
+    Openssh,
+    /// JSON Web Key
+    Jwk,
 }
 
 impl From<TextSignFormat> for &'static str {
     fn from(value: TextSignFormat) -> Self {
         match value {
-            TextSignFormat::Blake3 => "blacke3",
-            TextSignFormat::Ed2519 => "ed2519",
+            TextSignFormat::Blake3 => "blake3",
+            TextSignFormat::Ed25519 => "ed25519",
+            TextSignFormat::Ed25519ph => "ed25519ph",
         }
     }
 }
@@ -82,10 +144,6 @@ impl fmt::Display for TextSignFormat {
     }
 }
 
-fn parse_format(input: &str) -> anyhow::Result<TextSignFormat, anyhow::Error> {
-    input.parse()
-}
-
 impl CmdExecutor for TextSignOpts {
     async fn execute(&self) -> anyhow::Result<()> {
         let signed = process_text_sign(&self.input, &self.key, self.format)?;
@@ -102,17 +160,59 @@ impl CmdExecutor for TextVerifyOpts {
     }
 }
 
+impl CmdExecutor for TextEncryptOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        if self.output != "-" {
+            // a partial ciphertext can't be decrypted anyway, and the output may
+            // be the input file, which must not be truncated before it is read
+            return write_file_atomic(&self.output, |writer| {
+                process_text_encrypt(&mut reader, writer, self.key.as_deref())
+            });
+        }
+        let mut writer = get_writer(&self.output)?;
+        process_text_encrypt(&mut reader, &mut writer, self.key.as_deref())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextDecryptOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        let mut reader = get_reader(&self.input)?;
+        if self.output != "-" {
+            // don't leave unauthenticated plaintext behind, or truncate the input
+            // when it is the output file too
+            return write_file_atomic(&self.output, |writer| {
+                process_text_decrypt(&mut reader, writer, self.key.as_deref())
+            });
+        }
+        let mut writer = get_writer(&self.output)?;
+        process_text_decrypt(&mut reader, &mut writer, self.key.as_deref())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for TextKeyRekeyOpts {
+    async fn execute(&self) -> anyhow::Result<()> {
+        process_key_rekey(&self.key)?;
+        eprintln!("The passphrase of {} is changed", self.key);
+        Ok(())
+    }
+}
+
 impl CmdExecutor for TextKeyGenerateOpts {
     async fn execute(&self) -> anyhow::Result<()> {
-        let key = process_generate(self.format)?;
+        let key = process_generate(self.format, self.key_format, self.encrypt)?;
         match self.format {
             TextSignFormat::Blake3 => {
-                let name = self.output.join("blake3.txt");
-                fs::write(name, &key[0])?;
+                let name = Path::new(&self.output).join("blake3.txt");
+                write_secret_file(name, &key[0])?;
             }
-            TextSignFormat::Ed2519 => {
-                let name = &self.output;
-                fs::write(name.join("ed25519.sk"), &key[0])?;
+            TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => {
+                let name = Path::new(&self.output);
+                write_secret_file(name.join("ed25519.sk"), &key[0])?;
                 fs::write(name.join("ed25519.pk"), &key[1])?;
             }
         }
@@ -5,10 +5,22 @@ mod utils;
 pub use cli::*;
 use enum_dispatch::enum_dispatch;
 pub use process::{
-    process_csv, process_decode, process_encode, process_gen_pass, process_generate,
-    process_http_serve, process_text_sign, process_text_verify,
+    decode_otp_secret, gen_pass_entropy, load_policy, load_wordlist, otpauth_uri,
+    process_codec_decode, process_codec_encode, process_csv, process_csv_render,
+    process_datauri_decode, process_datauri_encode, process_decode, process_decode_lenient,
+    process_encode, process_gen_pass, process_gen_pass_bulk, process_gen_pass_policy,
+    process_gen_passphrase, process_gen_pattern, process_gen_token, process_gen_ulid,
+    process_gen_uuid, process_generate, process_hotp, process_hotp_verify, process_http_serve,
+    process_jwt_decode, process_jwt_sign, process_jwt_verify, process_key_rekey,
+    process_otp_secret, process_pack, process_pass_audit, process_text_decrypt,
+    process_text_encrypt, process_text_sign, process_text_verify, process_totp,
+    process_totp_verify, process_unpack, render_pass_records, unix_now, AuditReport, Bundle,
+    BundleEntry, CrackTimes, DataUri, GenPassConfig, JwtValidation, OtpConfig, PassRecord,
+    PasswordPolicy, PolicyClass, RenderedRow,
+};
+pub use utils::{
+    get_reader, get_writer, hexdump, is_binary, write_file_atomic, write_output, write_secret_file,
 };
-pub use utils::get_reader;
 
 #[allow(async_fn_in_trait)]
 #[enum_dispatch]
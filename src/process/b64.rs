@@ -1,33 +1,537 @@
-use crate::{get_reader, Base64Format};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+use crate::{get_reader, process_codec_decode, Base64Format, CodecFormat};
 use anyhow::{Ok, Result};
 use base64::{
-    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
-    Engine as _,
+    alphabet,
+    engine::{
+        general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD},
+        DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig,
+    },
+    read::DecoderReader,
+    write::EncoderWriter,
+    DecodeError,
 };
 
-pub fn process_encode(input: &str, format: Base64Format) -> Result<String> {
-    let mut reader = get_reader(input)?;
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
+/// how many of the last kept bytes `FilterReader` remembers, more than the decoder buffers
+const RECENT_LEN: usize = 4096;
+
+/// url-safe input is translated to the standard alphabet before it reaches this engine
+const AUTO: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// like AUTO, padding is dropped before it reaches this engine
+const LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// Which of the two alphabets the input may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alphabet {
+    Standard,
+    UrlSafe,
+    /// either one, but not both
+    Detect,
+    /// either one, even mixed
+    Any,
+}
+
+#[derive(Debug)]
+pub struct DataUri {
+    pub mime: String,
+    pub extension: String,
+    pub payload: Vec<u8>,
+}
+
+/// Encode everything from the reader into the writer, chunk by chunk.
+/// `wrap` overrides the line width of the format, `Some(0)` disables wrapping.
+pub fn process_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+    wrap: Option<usize>,
+) -> Result<()> {
+    if format == Base64Format::Auto {
+        anyhow::bail!("auto is only supported for decoding");
+    }
+    let width = wrap.or_else(|| default_wrap(format)).unwrap_or(0);
+    let mut writer = LineWrap::new(writer, width, format.line_ending());
+    let mut encoder = EncoderWriter::new(&mut writer, engine(format));
+    io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Decode everything from the reader into the writer, chunk by chunk, and return
+/// the number of decoded bytes. Whitespace (e.g. line wrapping) in the input is ignored.
+pub fn process_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: Base64Format,
+) -> Result<u64> {
+    let alphabet = match format {
+        Base64Format::UrlSafe | Base64Format::UrlSafePad => Alphabet::UrlSafe,
+        Base64Format::Auto => Alphabet::Detect,
+        _ => Alphabet::Standard,
+    };
+    decode(
+        reader,
+        writer,
+        Base64Filter::new(alphabet, false),
+        engine(format),
+    )
+}
+
+/// Like `process_decode`, but accept either alphabet, even mixed, and missing or
+/// extra padding
+pub fn process_decode_lenient(reader: &mut dyn Read, writer: &mut dyn Write) -> Result<u64> {
+    decode(
+        reader,
+        writer,
+        Base64Filter::new(Alphabet::Any, true),
+        &LENIENT,
+    )
+}
+
+fn decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    filter: Base64Filter,
+    engine: &GeneralPurpose,
+) -> Result<u64> {
+    let mut decoder = DecoderReader::new(filter.wrap(reader), engine);
+    match io::copy(&mut decoder, writer) {
+        Result::Ok(n) => Ok(n),
+        Err(e) => {
+            // the engine counts offsets without the whitespace, map them back
+            let msg = match e.get_ref().and_then(|e| e.downcast_ref::<DecodeError>()) {
+                Some(e) => decoder.into_inner().describe(e),
+                None => e.to_string(),
+            };
+            anyhow::bail!("Invalid base64 input: {}", msg)
+        }
+    }
+}
 
-    let encode = match format {
-        Base64Format::Standard => STANDARD.encode(buf),
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.encode(buf),
+/// Write the input as a `data:` uri. Unless given, the mime type is sniffed from
+/// the magic bytes, then from the file extension.
+pub fn process_datauri_encode(
+    input: &str,
+    writer: &mut dyn Write,
+    mime: Option<&str>,
+) -> Result<()> {
+    let mut reader = BufReader::new(get_reader(input)?);
+    let mime = match mime {
+        Some(mime) => mime.to_string(),
+        None => {
+            let head = reader.fill_buf()?;
+            match infer::get(head) {
+                Some(kind) => kind.mime_type().to_string(),
+                None => mime_guess::from_path(input)
+                    .first_or_octet_stream()
+                    .to_string(),
+            }
+        }
     };
-    Ok(encode)
+    write!(writer, "data:{};base64,", mime)?;
+    process_encode(&mut reader, writer, Base64Format::Standard, Some(0))
 }
 
-pub fn process_decode(input: &str, format: Base64Format) -> Result<Vec<u8>> {
-    let mut reader = get_reader(input)?;
+/// Parse a `data:` uri, the extension is guessed from the payload or the mime type
+pub fn process_datauri_decode(reader: &mut dyn Read) -> Result<DataUri> {
     let mut buf = String::new();
     reader.read_to_string(&mut buf)?;
-    // avoid accidental newlines
-    let buf = buf.trim();
+    let Some(uri) = buf.trim().strip_prefix("data:") else {
+        anyhow::bail!("Invalid data uri: missing the data: scheme");
+    };
+    let Some((meta, data)) = uri.split_once(',') else {
+        anyhow::bail!("Invalid data uri: missing the ',' before the payload");
+    };
+    let mut params = meta.split(';').collect::<Vec<_>>();
+    let is_base64 = params.last() == Some(&"base64");
+    if is_base64 {
+        params.pop();
+    }
+    // RFC 2397 default
+    let mime = match params.first() {
+        Some(mime) if mime.contains('/') => mime.to_string(),
+        _ => "text/plain".to_string(),
+    };
 
-    let decoded = match format {
-        Base64Format::Standard => STANDARD.decode(buf)?,
-        Base64Format::UrlSafe => URL_SAFE_NO_PAD.decode(buf)?,
+    let mut payload = Vec::new();
+    if is_base64 {
+        process_decode(&mut data.as_bytes(), &mut payload, Base64Format::Auto)?;
+    } else {
+        process_codec_decode(&mut data.as_bytes(), &mut payload, CodecFormat::Url)?;
+    }
+    let extension = match infer::get(&payload) {
+        Some(kind) => kind.extension(),
+        None => extension_for_mime(&mime),
     };
+    let extension = extension.to_string();
+    Ok(DataUri {
+        mime,
+        extension,
+        payload,
+    })
+}
+
+fn extension_for_mime(mime: &str) -> &'static str {
+    // mime_guess lists extensions alphabetically, e.g. "asm" comes first for text/plain
+    match mime {
+        "text/plain" => "txt",
+        "text/html" => "html",
+        "text/css" => "css",
+        "text/csv" => "csv",
+        "text/javascript" | "application/javascript" => "js",
+        "application/json" => "json",
+        "image/svg+xml" => "svg",
+        "image/jpeg" => "jpg",
+        _ => mime_guess::get_mime_extensions_str(mime)
+            .and_then(|exts| exts.first().copied())
+            .unwrap_or("bin"),
+    }
+}
+
+fn engine(format: Base64Format) -> &'static GeneralPurpose {
+    match format {
+        Base64Format::Standard | Base64Format::Mime | Base64Format::Pem => &STANDARD,
+        Base64Format::StandardNoPad => &STANDARD_NO_PAD,
+        Base64Format::UrlSafe => &URL_SAFE_NO_PAD,
+        Base64Format::UrlSafePad => &URL_SAFE,
+        Base64Format::Auto => &AUTO,
+    }
+}
+
+fn default_wrap(format: Base64Format) -> Option<usize> {
+    match format {
+        Base64Format::Mime => Some(76),
+        Base64Format::Pem => Some(64),
+        _ => None,
+    }
+}
+
+/// A writer that breaks the output into lines of `width` bytes, 0 means no wrapping
+struct LineWrap<W> {
+    inner: W,
+    width: usize,
+    line_ending: &'static str,
+    column: usize,
+}
+
+impl<W: Write> LineWrap<W> {
+    fn new(inner: W, width: usize, line_ending: &'static str) -> Self {
+        Self {
+            inner,
+            width,
+            line_ending,
+            column: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for LineWrap<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.width == 0 {
+            return self.inner.write(buf);
+        }
+        let mut written = 0;
+        while written < buf.len() {
+            if self.column == self.width {
+                self.inner.write_all(self.line_ending.as_bytes())?;
+                self.column = 0;
+            }
+            let n = (self.width - self.column).min(buf.len() - written);
+            self.inner.write_all(&buf[written..written + n])?;
+            self.column += n;
+            written += n;
+        }
+        io::Result::Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Settings for `FilterReader`
+struct Base64Filter {
+    alphabet: Alphabet,
+    drop_padding: bool,
+}
+
+/// A reader that drops whitespace, checks every byte against the alphabet and maps
+/// the url-safe alphabet onto the standard one when both are accepted. Errors point
+/// at the byte offset in the original input.
+struct FilterReader<R> {
+    inner: R,
+    filter: Base64Filter,
+    offset: usize,
+    /// how many bytes were kept, and the original offset and byte of the last ones
+    kept: usize,
+    recent: VecDeque<(usize, u8)>,
+    first_padding: Option<usize>,
+    padding: bool,
+    standard: bool,
+    url_safe: bool,
+}
+
+impl Base64Filter {
+    fn new(alphabet: Alphabet, drop_padding: bool) -> Self {
+        Self {
+            alphabet,
+            drop_padding,
+        }
+    }
+
+    fn wrap<R: Read>(self, inner: R) -> FilterReader<R> {
+        FilterReader {
+            inner,
+            filter: self,
+            offset: 0,
+            kept: 0,
+            recent: VecDeque::with_capacity(RECENT_LEN),
+            first_padding: None,
+            padding: false,
+            standard: false,
+            url_safe: false,
+        }
+    }
+}
+
+impl<R: Read> FilterReader<R> {
+    /// Returns the byte to keep, if any, and remembers where it came from
+    fn filter(&mut self, b: u8) -> io::Result<Option<u8>> {
+        let offset = self.offset;
+        let kept = self.check(b)?;
+        if kept.is_some() {
+            if self.recent.len() == RECENT_LEN {
+                self.recent.pop_front();
+            }
+            self.recent.push_back((offset, b));
+            self.kept += 1;
+        }
+        io::Result::Ok(kept)
+    }
+
+    fn check(&mut self, b: u8) -> io::Result<Option<u8>> {
+        let offset = self.offset;
+        self.offset += 1;
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if b.is_ascii_whitespace() {
+            return io::Result::Ok(None);
+        }
+        if b == b'=' {
+            self.padding = true;
+            self.first_padding.get_or_insert(offset);
+            return io::Result::Ok((!self.filter.drop_padding).then_some(b));
+        }
+        if self.padding && !self.filter.drop_padding {
+            return Err(invalid(format!(
+                "unexpected {:?} after padding at byte offset {}",
+                b as char, offset
+            )));
+        }
+        let alphabet = self.filter.alphabet;
+        let b = match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => b,
+            b'+' | b'/' if alphabet != Alphabet::UrlSafe => {
+                self.standard = true;
+                b
+            }
+            b'-' | b'_' if alphabet == Alphabet::UrlSafe => b,
+            b'-' | b'_' if alphabet != Alphabet::Standard => {
+                self.url_safe = true;
+                if b == b'-' {
+                    b'+'
+                } else {
+                    b'/'
+                }
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "invalid character {:?} (0x{:02x}) at byte offset {}",
+                    b as char, b, offset
+                )))
+            }
+        };
+        if alphabet == Alphabet::Detect && self.standard && self.url_safe {
+            return Err(invalid(format!(
+                "the standard and url-safe alphabets are mixed at byte offset {}",
+                offset
+            )));
+        }
+        io::Result::Ok(Some(b))
+    }
+}
+
+impl<R> FilterReader<R> {
+    /// An engine error, with its offset in the filtered bytes mapped back to the input
+    fn describe(&self, e: &DecodeError) -> String {
+        let original = |filtered: usize| {
+            let back = self.kept.checked_sub(filtered)?;
+            let i = self.recent.len().checked_sub(back)?;
+            self.recent.get(i).copied()
+        };
+        let end = self.recent.back().map_or(0, |(offset, _)| offset + 1);
+        match *e {
+            DecodeError::InvalidByte(i, _) | DecodeError::InvalidLastSymbol(i, _) => {
+                let Some((offset, b)) = original(i) else {
+                    return e.to_string();
+                };
+                let what = match e {
+                    DecodeError::InvalidByte(..) => "unexpected character",
+                    _ => "the last character has trailing bits set,",
+                };
+                format!(
+                    "{} {:?} (0x{:02x}) at byte offset {}",
+                    what, b as char, b, offset
+                )
+            }
+            DecodeError::InvalidLength(_) => {
+                format!(
+                    "the input ends in the middle of a block at byte offset {}",
+                    end
+                )
+            }
+            DecodeError::InvalidPadding => match self.first_padding {
+                Some(offset) => format!("invalid padding at byte offset {}", offset),
+                None => format!("missing padding at byte offset {}", end),
+            },
+        }
+    }
+}
+
+impl<R: Read> Read for FilterReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return io::Result::Ok(0);
+            }
+            let mut len = 0;
+            for i in 0..n {
+                if let Some(b) = self.filter(buf[i])? {
+                    buf[len] = b;
+                    len += 1;
+                }
+            }
+            // a chunk of only whitespace is not EOF, keep reading
+            if len > 0 {
+                return io::Result::Ok(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_decode_wrapped() -> Result<()> {
+        let mut input = "aGVs\nbG8g\r\n d29y bGQ=\n".as_bytes();
+        let mut output = Vec::new();
+        process_decode(&mut input, &mut output, Base64Format::Standard)?;
+        assert_eq!(output, b"hello world");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_encode_pem_wrap() -> Result<()> {
+        let mut input = [0u8; 60].as_slice();
+        let mut output = Vec::new();
+        process_encode(&mut input, &mut output, Base64Format::Pem, None)?;
+        let output = String::from_utf8(output)?;
+        let lines = output.split('\n').map(str::len).collect::<Vec<_>>();
+        assert_eq!(lines, [64, 16]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_datauri_decode() -> Result<()> {
+        let png = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==";
+        let uri = process_datauri_decode(&mut png.as_bytes())?;
+        assert_eq!(
+            (uri.mime.as_str(), uri.extension.as_str()),
+            ("image/png", "png")
+        );
+        let text = process_datauri_decode(&mut "data:,hello%20world".as_bytes())?;
+        assert_eq!(text.mime, "text/plain");
+        assert_eq!(text.payload, b"hello world");
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_decode_auto() -> Result<()> {
+        for input in ["-_8=", "-_8", "+/8=", "+/8"] {
+            let mut output = Vec::new();
+            process_decode(&mut input.as_bytes(), &mut output, Base64Format::Auto)?;
+            assert_eq!(output, [0xfb, 0xff]);
+        }
+        let mut output = Vec::new();
+        let ret = process_decode(&mut "+_8=".as_bytes(), &mut output, Base64Format::Auto);
+        assert!(ret.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_decode_error_offset() {
+        let mut output = Vec::new();
+        let ret = process_decode(
+            &mut "aGVs\nbG8*".as_bytes(),
+            &mut output,
+            Base64Format::Standard,
+        );
+        assert_eq!(
+            ret.unwrap_err().to_string(),
+            "Invalid base64 input: invalid character '*' (0x2a) at byte offset 8"
+        );
+    }
+
+    #[test]
+    fn test_process_decode_engine_error_offset() {
+        let decode = |input: &str| {
+            let ret = process_decode(
+                &mut input.as_bytes(),
+                &mut Vec::new(),
+                Base64Format::Standard,
+            );
+            ret.unwrap_err().to_string()
+        };
+        assert_eq!(
+            decode("aGVs bG8g d29y bGR="),
+            "Invalid base64 input: the last character has trailing bits set, 'R' (0x52) at byte offset 17"
+        );
+        assert_eq!(
+            decode("aGVs\nbG8\n"),
+            "Invalid base64 input: missing padding at byte offset 8"
+        );
+        assert_eq!(
+            decode("aGVs\nbG8==\n"),
+            "Invalid base64 input: unexpected character '=' (0x3d) at byte offset 8"
+        );
+        assert_eq!(
+            decode("aGVs\nb\n"),
+            "Invalid base64 input: the input ends in the middle of a block at byte offset 6"
+        );
+    }
 
-    Ok(decoded)
+    #[test]
+    fn test_process_decode_lenient() -> Result<()> {
+        for input in ["-_8", "+_8==", "-/8=\n=", "+/8"] {
+            let mut output = Vec::new();
+            process_decode_lenient(&mut input.as_bytes(), &mut output)?;
+            assert_eq!(output, [0xfb, 0xff]);
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,250 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Ok, Result};
+use serde::{Deserialize, Serialize};
+
+use super::csv_convert::serialize_output;
+use crate::{process_decode, process_encode, Base64Format, OutputFormat};
+
+/// A directory of files embedded into one json/yaml document
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Bundle {
+    /// keyed by the path relative to the packed directory, always with '/' separators
+    pub files: BTreeMap<String, BundleEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub size: u64,
+    /// hex encoded BLAKE3 hash of the original contents
+    pub blake3: String,
+    /// standard base64
+    pub content: String,
+}
+
+/// Hashes and counts everything that is read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+    size: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        io::Result::Ok(n)
+    }
+}
+
+pub fn process_pack(dir: &str, format: OutputFormat) -> Result<String> {
+    let root = Path::new(dir);
+    let mut bundle = Bundle::default();
+    for path in list_files(root)? {
+        let name = path
+            .strip_prefix(root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut reader = HashingReader {
+            inner: File::open(&path)?,
+            hasher: blake3::Hasher::new(),
+            size: 0,
+        };
+        let mut content = Vec::new();
+        process_encode(&mut reader, &mut content, Base64Format::Standard, Some(0))?;
+        let entry = BundleEntry {
+            size: reader.size,
+            blake3: reader.hasher.finalize().to_hex().to_string(),
+            content: String::from_utf8(content)?,
+        };
+        bundle.files.insert(name, entry);
+    }
+    serialize_output(&bundle, format)
+}
+
+/// Restore the files of a bundle into `dir`, nothing is written unless every file
+/// verifies and has a free place to go. Existing files are only replaced with
+/// `force`, and symlinks under `dir` are never followed. Returns the restored paths.
+pub fn process_unpack(input: &str, dir: &str, force: bool) -> Result<Vec<PathBuf>> {
+    // yaml is a superset of json, so this reads both formats
+    let bundle: Bundle = serde_yaml::from_reader(File::open(input)?)?;
+    let root = Path::new(dir);
+    let mut files = BTreeMap::new();
+    for (name, entry) in &bundle.files {
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            anyhow::bail!("Invalid path {:?} in bundle", name);
+        }
+        // the size is untrusted, so let the buffer grow with the decoded data
+        let mut content = Vec::new();
+        process_decode(
+            &mut entry.content.as_bytes(),
+            &mut content,
+            Base64Format::Standard,
+        )?;
+        if content.len() as u64 != entry.size {
+            anyhow::bail!(
+                "Size mismatch for {}: expected {}, got {}",
+                name,
+                entry.size,
+                content.len()
+            );
+        }
+        let digest = blake3::hash(&content).to_hex();
+        if digest.as_str() != entry.blake3 {
+            anyhow::bail!("BLAKE3 digest mismatch for {}", name);
+        }
+        // "a//b" and "a/b" are the same file
+        if files.insert(relative.to_path_buf(), content).is_some() {
+            anyhow::bail!("Duplicate path {:?} in bundle", name);
+        }
+    }
+    for relative in files.keys() {
+        check_target(root, relative, &files, force)?;
+    }
+
+    let mut ret = Vec::with_capacity(files.len());
+    for (relative, content) in files {
+        let path = root.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if force && path.symlink_metadata().is_ok() {
+            // replace a link itself rather than the file it points to
+            fs::remove_file(&path)?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        file.write_all(&content)?;
+        ret.push(path);
+    }
+    Ok(ret)
+}
+
+/// Fail if `relative` can't be written under `root`: a file of the bundle or an
+/// existing file or symlink is in the way of its directories, or it exists already
+fn check_target(
+    root: &Path,
+    relative: &Path,
+    files: &BTreeMap<PathBuf, Vec<u8>>,
+    force: bool,
+) -> Result<()> {
+    let mut dir = PathBuf::new();
+    let parents = relative
+        .parent()
+        .map(|p| p.components())
+        .into_iter()
+        .flatten();
+    for component in parents {
+        dir.push(component);
+        if files.contains_key(&dir) {
+            anyhow::bail!(
+                "{} is both a file and a directory in the bundle",
+                dir.display()
+            );
+        }
+        match root.join(&dir).symlink_metadata() {
+            Result::Ok(meta) if meta.is_dir() => {}
+            Result::Ok(_) => anyhow::bail!(
+                "{} is in the way of {}, it is not a directory",
+                root.join(&dir).display(),
+                relative.display()
+            ),
+            Err(_) => {}
+        }
+    }
+    let path = root.join(relative);
+    match path.symlink_metadata() {
+        Result::Ok(meta) if meta.is_dir() => {
+            anyhow::bail!("{} exists and is a directory", path.display())
+        }
+        Result::Ok(_) if !force => {
+            anyhow::bail!("{} exists, use --force to replace it", path.display())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// All regular files under `dir`, sorted. Symlinks are skipped, so a link loop
+/// can't make this walk forever.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut ret = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            // unlike `Path::is_dir`, this doesn't follow symlinks
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                ret.push(entry.path());
+            }
+        }
+    }
+    ret.sort();
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_pack_unpack() -> Result<()> {
+        let dir = TestDir::new("pack");
+        fs::create_dir_all(dir.join("src/sub"))?;
+        fs::write(dir.join("src/a.txt"), "hello")?;
+        fs::write(dir.join("src/sub/b.bin"), [0u8, 1, 2, 255])?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("..", dir.join("src/sub/loop"))?;
+
+        let bundle = process_pack(dir.join("src").to_str().unwrap(), OutputFormat::Json)?;
+        let input = dir.join("bundle.json");
+        fs::write(&input, &bundle)?;
+        let (input, out) = (input.to_str().unwrap(), dir.join("out"));
+        let restored = process_unpack(input, out.to_str().unwrap(), false)?;
+        assert_eq!(restored.len(), 2);
+        assert_eq!(fs::read(out.join("sub/b.bin"))?, [0u8, 1, 2, 255]);
+
+        // existing files are only replaced with force, and links are not followed
+        let out = out.to_str().unwrap();
+        assert!(process_unpack(input, out, false).is_err());
+        fs::remove_file(dir.join("out/a.txt"))?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("src/a.txt"), dir.join("out/a.txt"))?;
+        process_unpack(input, out, true)?;
+        assert_eq!(fs::read(dir.join("out/a.txt"))?, b"hello");
+        assert!(!fs::symlink_metadata(dir.join("out/a.txt"))?.is_symlink());
+
+        // a.txt sorts first but must not be written when sub/b.bin is bad
+        let tampered = bundle.replace(r#""content": "AAEC/w==""#, r#""content": "AAEC/g==""#);
+        assert_ne!(tampered, bundle);
+        fs::write(input, tampered)?;
+        let out = dir.join("tampered");
+        assert!(process_unpack(input, out.to_str().unwrap(), false).is_err());
+        assert!(!out.exists());
+
+        // "a" and "a/b" can't both be written
+        let clash = bundle.replace(r#""sub/b.bin""#, r#""a.txt/b.bin""#);
+        assert_ne!(clash, bundle);
+        fs::write(input, clash)?;
+        assert!(process_unpack(input, out.to_str().unwrap(), false).is_err());
+        assert!(!out.exists());
+        Ok(())
+    }
+}
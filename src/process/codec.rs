@@ -0,0 +1,291 @@
+use std::io::{Read, Write};
+
+use anyhow::{Ok, Result};
+use data_encoding::{BASE32, BASE32_NOPAD_NOCASE, HEXLOWER, HEXLOWER_PERMISSIVE};
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::{process_decode, process_encode, utils::SkipWhitespace, Base64Format, CodecFormat};
+
+/// RFC 3986 unreserved characters are left as is
+pub(super) const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Blocks per chunk for the codecs that can stream
+const BLOCKS_PER_CHUNK: usize = 4096;
+
+pub trait Codec {
+    /// Encode the data from the reader into the writer
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+    /// Decode the data from the reader into the writer, whitespace is ignored
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()>;
+}
+
+pub struct Base64;
+
+pub struct Base32;
+
+pub struct Base58;
+
+/// ZeroMQ Z85
+pub struct Base85;
+
+/// Adobe/btoa Ascii85, without the `<~ ~>` delimiters
+pub struct Ascii85;
+
+pub struct Hex;
+
+pub struct Percent;
+
+impl Codec for Base64 {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        process_encode(reader, writer, Base64Format::Standard, None)
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        process_decode(reader, writer, Base64Format::Auto)?;
+        Ok(())
+    }
+}
+
+impl Codec for Base32 {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(reader, 5, |chunk| {
+            writer.write_all(BASE32.encode(chunk).as_bytes())?;
+            Ok(())
+        })
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        // padding is optional, TOTP secrets usually come without it
+        for_each_chunk(&mut SkipWhitespace::new(reader), 8, |chunk| {
+            let end = chunk.iter().rposition(|&b| b != b'=').map_or(0, |i| i + 1);
+            writer.write_all(&BASE32_NOPAD_NOCASE.decode(&chunk[..end])?)?;
+            Ok(())
+        })
+    }
+}
+
+impl Codec for Base58 {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        // base58 is a big number conversion, it can't stream
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        writer.write_all(bs58::encode(buf).into_string().as_bytes())?;
+        Ok(())
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        let mut buf = Vec::new();
+        SkipWhitespace::new(reader).read_to_end(&mut buf)?;
+        writer.write_all(&bs58::decode(buf).into_vec()?)?;
+        Ok(())
+    }
+}
+
+impl Codec for Base85 {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(reader, 4, |chunk| {
+            writer.write_all(z85::encode(chunk).as_bytes())?;
+            Ok(())
+        })
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(&mut SkipWhitespace::new(reader), 5, |chunk| {
+            writer.write_all(&z85::decode(chunk)?)?;
+            Ok(())
+        })
+    }
+}
+
+impl Codec for Ascii85 {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(reader, 4, |chunk| {
+            let mut out = Vec::with_capacity(chunk.len() / 4 * 5 + 5);
+            for group in chunk.chunks(4) {
+                let mut bytes = [0u8; 4];
+                bytes[..group.len()].copy_from_slice(group);
+                let mut value = u32::from_be_bytes(bytes);
+                if group.len() == 4 && value == 0 {
+                    out.push(b'z');
+                    continue;
+                }
+                let mut digits = [0u8; 5];
+                for digit in digits.iter_mut().rev() {
+                    *digit = (value % 85) as u8 + b'!';
+                    value /= 85;
+                }
+                // a partial group of n bytes only needs n + 1 digits
+                out.extend_from_slice(&digits[..group.len() + 1]);
+            }
+            writer.write_all(&out)?;
+            Ok(())
+        })
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        // 'z' breaks fixed size groups, so we can't chunk the input
+        let mut buf = Vec::new();
+        SkipWhitespace::new(reader).read_to_end(&mut buf)?;
+        let buf = buf.strip_prefix(b"<~").unwrap_or(&buf);
+        let buf = buf.strip_suffix(b"~>").unwrap_or(buf);
+
+        let mut out = Vec::with_capacity(buf.len() / 5 * 4 + 4);
+        let mut group = Vec::with_capacity(5);
+        for (i, &b) in buf.iter().enumerate() {
+            match b {
+                b'z' if group.is_empty() => out.extend_from_slice(&[0; 4]),
+                b'!'..=b'u' => {
+                    group.push(b);
+                    if group.len() == 5 {
+                        out.extend_from_slice(&decode_ascii85_group(&group, i)?);
+                        group.clear();
+                    }
+                }
+                _ => anyhow::bail!("Invalid ascii85 character {:?} at {}", b as char, i),
+            }
+        }
+        match group.len() {
+            0 => {}
+            1 => anyhow::bail!("Invalid ascii85 data: a final group can't be a single char"),
+            n => {
+                group.resize(5, b'u');
+                out.extend_from_slice(&decode_ascii85_group(&group, buf.len())?[..n - 1]);
+            }
+        }
+        writer.write_all(&out)?;
+        Ok(())
+    }
+}
+
+impl Codec for Hex {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(reader, 1, |chunk| {
+            writer.write_all(HEXLOWER.encode(chunk).as_bytes())?;
+            Ok(())
+        })
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(&mut SkipWhitespace::new(reader), 2, |chunk| {
+            writer.write_all(&HEXLOWER_PERMISSIVE.decode(chunk)?)?;
+            Ok(())
+        })
+    }
+}
+
+impl Codec for Percent {
+    fn encode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        for_each_chunk(reader, 1, |chunk| {
+            for s in percent_encode(chunk, URL_ENCODE_SET) {
+                writer.write_all(s.as_bytes())?;
+            }
+            Ok(())
+        })
+    }
+
+    fn decode(&self, reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+        // an escape sequence may span two chunks, so read it all
+        let mut buf = Vec::new();
+        SkipWhitespace::new(reader).read_to_end(&mut buf)?;
+        writer.write_all(&percent_decode(&buf).collect::<Vec<u8>>())?;
+        Ok(())
+    }
+}
+
+pub fn process_codec_encode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: CodecFormat,
+) -> Result<()> {
+    codec(format).encode(reader, writer)
+}
+
+pub fn process_codec_decode(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    format: CodecFormat,
+) -> Result<()> {
+    codec(format).decode(reader, writer)
+}
+
+fn codec(format: CodecFormat) -> Box<dyn Codec> {
+    match format {
+        CodecFormat::Base64 => Box::new(Base64),
+        CodecFormat::Base32 => Box::new(Base32),
+        CodecFormat::Base58 => Box::new(Base58),
+        CodecFormat::Base85 => Box::new(Base85),
+        CodecFormat::Ascii85 => Box::new(Ascii85),
+        CodecFormat::Hex => Box::new(Hex),
+        CodecFormat::Url => Box::new(Percent),
+    }
+}
+
+fn decode_ascii85_group(group: &[u8], pos: usize) -> Result<[u8; 4]> {
+    let value = group
+        .iter()
+        .fold(0u64, |acc, &b| acc * 85 + (b - b'!') as u64);
+    let value = u32::try_from(value)
+        .map_err(|_| anyhow::anyhow!("Invalid ascii85 group ending at {}", pos))?;
+    Ok(value.to_be_bytes())
+}
+
+/// Read the whole input in chunks whose size is a multiple of `block`, only the
+/// last chunk may be shorter
+fn for_each_chunk(
+    reader: &mut dyn Read,
+    block: usize,
+    mut f: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut buf = vec![0u8; block * BLOCKS_PER_CHUNK];
+    loop {
+        let mut len = 0;
+        while len < buf.len() {
+            let n = reader.read(&mut buf[len..])?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+        }
+        if len > 0 {
+            f(&buf[..len])?;
+        }
+        if len < buf.len() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(format: CodecFormat, data: &[u8], encoded: &str) -> Result<()> {
+        let mut output = Vec::new();
+        process_codec_encode(&mut &data[..], &mut output, format)?;
+        assert_eq!(String::from_utf8(output)?, encoded);
+        let mut output = Vec::new();
+        process_codec_decode(&mut encoded.as_bytes(), &mut output, format)?;
+        assert_eq!(output, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_codec_round_trip() -> Result<()> {
+        round_trip(CodecFormat::Base64, b"hello", "aGVsbG8=")?;
+        round_trip(CodecFormat::Base32, b"hello", "NBSWY3DP")?;
+        round_trip(CodecFormat::Base58, b"hello", "Cn8eVZg")?;
+        round_trip(
+            CodecFormat::Base85,
+            b"\x86\x4f\xd2\x6f\xb5\x59\xf7\x5b",
+            "HelloWorld",
+        )?;
+        round_trip(CodecFormat::Ascii85, b"hell\0\0\0\0o", "BOu!rzDZ")?;
+        round_trip(CodecFormat::Hex, b"hello", "68656c6c6f")?;
+        round_trip(CodecFormat::Url, b"a b/c~", "a%20b%2Fc~")?;
+        Ok(())
+    }
+}
@@ -1,24 +1,68 @@
 use std::fs;
 
+use anyhow::Result;
 use csv::Reader;
-use serde::{Deserialize, Serialize};
+use handlebars::Handlebars;
+use serde::Serialize;
 use serde_json::Value;
 
 use crate::cli::OutputFormat;
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
+#[derive(Debug)]
+pub struct RenderedRow {
+    /// rendered file name, only present when a name template is given
+    pub name: Option<String>,
+    pub content: String,
 }
 
 pub fn process_csv(input: &str, output: String, format: OutputFormat) -> anyhow::Result<()> {
+    let ret = read_csv_rows(input)?;
+    let content = serialize_output(&ret, format)?;
+
+    fs::write(output, content)?;
+    Ok(())
+}
+
+pub fn serialize_output(value: &impl Serialize, format: OutputFormat) -> Result<String> {
+    let content = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value)?,
+        // OutputFormat::Toml => toml::to_string(value)?,
+        OutputFormat::Yaml => serde_yaml::to_string(value)?,
+    };
+    Ok(content)
+}
+
+/// Render `template` once per CSV row. Each row is exposed to the template as the
+/// same json object `process_csv` builds, e.g. `{{Name}}` or `{{[Kit Number]}}`.
+pub fn process_csv_render(
+    input: &str,
+    template: &str,
+    name: Option<&str>,
+) -> Result<Vec<RenderedRow>> {
+    let rows = read_csv_rows(input)?;
+    let mut hbs = Handlebars::new();
+    // output is config files or emails, not html
+    hbs.register_escape_fn(handlebars::no_escape);
+    // a typo in a field name should fail loudly instead of rendering blanks
+    hbs.set_strict_mode(true);
+    hbs.register_template_string("template", fs::read_to_string(template)?)?;
+    if let Some(name) = name {
+        hbs.register_template_string("name", name)?;
+    }
+
+    let mut ret = Vec::with_capacity(rows.len());
+    for row in rows {
+        let name = match name {
+            Some(_) => Some(hbs.render("name", &row)?),
+            None => None,
+        };
+        let content = hbs.render("template", &row)?;
+        ret.push(RenderedRow { name, content });
+    }
+    Ok(ret)
+}
+
+fn read_csv_rows(input: &str) -> Result<Vec<Value>> {
     let mut reader = Reader::from_path(input)?;
     let mut ret = Vec::with_capacity(128);
     let headers = reader.headers()?.clone();
@@ -31,12 +75,26 @@ pub fn process_csv(input: &str, output: String, format: OutputFormat) -> anyhow:
         let json_value = headers.iter().zip(record.iter()).collect::<Value>();
         ret.push(json_value);
     }
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        // OutputFormat::Toml => toml::to_string(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
-    };
+    Ok(ret)
+}
 
-    fs::write(output, content)?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_process_csv_render() -> Result<()> {
+        let dir = TestDir::new("csv_render");
+        let template = dir.join("template.hbs");
+        fs::write(&template, "{{Name}} #{{[Kit Number]}}\n")?;
+        let rows = process_csv_render(
+            "assets/juventus.csv",
+            template.to_str().unwrap(),
+            Some("{{Name}}.txt"),
+        )?;
+        assert_eq!(rows[0].name.as_deref(), Some("Wojciech Szczesny.txt"));
+        assert_eq!(rows[0].content, "Wojciech Szczesny #1\n");
+        Ok(())
+    }
 }
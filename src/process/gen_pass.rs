@@ -1,56 +1,804 @@
+use std::{collections::HashSet, fs};
+
 use anyhow::Ok;
-// use rand::Rng;
+use rand::rngs::OsRng;
 use rand::seq::SliceRandom;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::Serialize;
+use zxcvbn::zxcvbn;
 
-const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"123456789";
-const SYMBOL: &[u8] = b"!@#$%^&*()_";
-
-pub fn process_gen_pass(
-    length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    symbol: bool,
-) -> anyhow::Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
+use super::csv_convert::serialize_output;
+use crate::{GenPassFormat, OutputFormat};
+
+pub(super) const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+pub(super) const LOWER: &[u8] = b"abcdefghijkmnopqrstuvwxyz";
+pub(super) const NUMBER: &[u8] = b"123456789";
+pub(super) const SYMBOL: &[u8] = b"!@#$%^&*()_";
+/// easily mistaken for each other in some fonts, left out of the classes above
+pub(super) const AMBIGUOUS: &[u8] = b"IOl0";
+/// pronounceable passwords alternate these
+const CONSONANTS: &[u8] = b"bcdfghjklmnprstvwz";
+const VOWELS: &[u8] = b"aeiou";
+/// how many rounds of duplicates in a row before bulk generation gives up
+pub(super) const MAX_ATTEMPTS: usize = 1000;
+/// below this many passwords a thread pool isn't worth it
+const PARALLEL_THRESHOLD: usize = 256;
+/// BIP-0039 english list, 2048 short and distinct words
+const WORDLIST: &str = include_str!("../../assets/wordlist.txt");
+
+#[derive(Debug, Clone)]
+pub struct GenPassConfig {
+    pub length: u8,
+    pub upper: bool,
+    pub lower: bool,
+    pub number: bool,
+    pub symbol: bool,
+    /// every enabled class gets at least one char, these raise that minimum
+    pub min_upper: u8,
+    pub min_digits: u8,
+    pub min_symbols: u8,
+    /// replaces the built-in classes, e.g. "a-zA-Z0-9", its chars are sorted into
+    /// classes and classes it leaves empty are not required
+    pub charset: Option<String>,
+    /// replaces the built-in symbol class
+    pub symbols: Option<String>,
+    /// chars that are never used
+    pub exclude: String,
+    /// drops the lookalikes from a custom charset or symbols too
+    pub no_ambiguous: bool,
+    /// adds the lookalikes back to the built-in classes
+    pub lookalikes: bool,
+    /// no char is the same as the one before it
+    pub no_repeat: bool,
+    /// alternate consonants and vowels, digits and symbols go at the end
+    pub pronounceable: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PassRecord {
+    pub password: String,
+    /// zxcvbn score from 0 to 4
+    pub score: u8,
+    pub entropy_bits: f64,
+}
+
+pub(super) struct CharClass {
+    pub(super) name: &'static str,
+    pub(super) chars: Vec<u8>,
+    /// how many chars of this class the password needs
+    pub(super) min: usize,
+}
+
+impl CharClass {
+    /// digits and symbols go after the syllables of a pronounceable password
+    fn is_tail(&self) -> bool {
+        matches!(self.name, "number" | "symbol")
+    }
+}
+
+impl Default for GenPassConfig {
+    fn default() -> Self {
+        Self {
+            length: 16,
+            upper: true,
+            lower: true,
+            number: true,
+            symbol: true,
+            min_upper: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            charset: None,
+            symbols: None,
+            exclude: String::new(),
+            no_ambiguous: false,
+            lookalikes: false,
+            no_repeat: false,
+            pronounceable: false,
+        }
+    }
+}
+
+pub fn process_gen_pass(config: &GenPassConfig) -> anyhow::Result<String> {
+    if config.length == 0 {
+        anyhow::bail!("Password length must be greater than 0");
+    }
+    if config.pronounceable && config.charset.is_some() {
+        anyhow::bail!("A pronounceable password can't use a custom charset");
+    }
+    if config.no_ambiguous && config.lookalikes {
+        anyhow::bail!("Lookalike chars can't be both added and left out");
+    }
+    let classes = char_classes(config)?;
+    let required = classes.iter().map(|class| class.min).sum::<usize>();
+    if required > config.length as usize {
+        anyhow::bail!(
+            "Password length {} is too short for the {} required characters",
+            config.length,
+            required
+        );
+    }
+
+    if config.no_repeat {
+        let pool = classes.iter().map(|c| c.chars.len()).sum::<usize>();
+        if pool < 2 && config.length > 1 {
+            anyhow::bail!("Can't avoid repeated chars with a single-character charset");
+        }
+        // the same char needs another one between each of its uses
+        let room = (config.length as usize).div_ceil(2);
+        if let Some(class) = classes.iter().find(|c| c.chars.len() == 1 && c.min > room) {
+            anyhow::bail!(
+                "Can't avoid repeated chars with {} {} chars that can only be {:?}",
+                class.min,
+                class.name,
+                class.chars[0] as char
+            );
+        }
+    }
+
+    // OsRng asks the OS for every value, and choose() samples without modulo bias
+    let mut rng = OsRng;
+    let password = if config.pronounceable {
+        gen_pronounceable(config.length as usize, &classes, config.no_repeat, &mut rng)?
+    } else {
+        gen_random(config.length as usize, &classes, config.no_repeat, &mut rng)?
+    };
+    Ok(String::from_utf8(password)?)
+}
 
-    if upper {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("upper won't be empty"))
+/// A built-in class, with the lookalikes of the same kind added back if wanted
+pub(super) fn builtin_class(chars: &[u8], lookalikes: bool) -> Vec<u8> {
+    let kind = |c: &u8| {
+        (
+            c.is_ascii_uppercase(),
+            c.is_ascii_lowercase(),
+            c.is_ascii_digit(),
+        )
+    };
+    let mut ret = chars.to_vec();
+    if lookalikes {
+        ret.extend(
+            AMBIGUOUS
+                .iter()
+                .filter(|c| chars.iter().any(|d| kind(d) == kind(c))),
+        );
+        ret.sort_unstable();
     }
+    ret
+}
+
+/// The enabled classes after the charset, symbols and exclusions are applied
+pub(super) fn char_classes(config: &GenPassConfig) -> anyhow::Result<Vec<CharClass>> {
+    let charset = config
+        .charset
+        .as_deref()
+        .map(|spec| parse_charset(spec, true))
+        .transpose()?;
+    let from_charset = |default: &[u8], filter: fn(&u8) -> bool| match &charset {
+        Some(chars) => chars.iter().copied().filter(filter).collect(),
+        None => builtin_class(default, config.lookalikes),
+    };
+    let symbols = match config.symbols.as_deref() {
+        Some(spec) => {
+            let symbols = parse_charset(spec, false)?;
+            if symbols.iter().any(u8::is_ascii_alphanumeric) {
+                anyhow::bail!("Symbols can't contain letters or digits: {:?}", spec);
+            }
+            symbols
+        }
+        None => from_charset(SYMBOL, |c| !c.is_ascii_alphanumeric()),
+    };
+    let classes = [
+        (
+            "uppercase",
+            from_charset(UPPER, u8::is_ascii_uppercase),
+            config.upper,
+            config.min_upper,
+        ),
+        (
+            "lowercase",
+            from_charset(LOWER, u8::is_ascii_lowercase),
+            config.lower,
+            0,
+        ),
+        (
+            "number",
+            from_charset(NUMBER, u8::is_ascii_digit),
+            config.number,
+            config.min_digits,
+        ),
+        ("symbol", symbols, config.symbol, config.min_symbols),
+    ];
 
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("upper won't be empty"))
+    let mut ret = Vec::new();
+    for (name, mut chars, enabled, min) in classes {
+        if !enabled {
+            if min > 0 {
+                anyhow::bail!(
+                    "A minimum of {} {} chars is set, but {} chars are disabled",
+                    min,
+                    name,
+                    name
+                );
+            }
+            continue;
+        }
+        let excluded = |c: &u8| {
+            config.exclude.as_bytes().contains(c) || config.no_ambiguous && AMBIGUOUS.contains(c)
+        };
+        chars.retain(|c| !excluded(c));
+        if chars.is_empty() {
+            // a charset may leave out whole classes on purpose
+            if min == 0 && charset.is_some() {
+                continue;
+            }
+            anyhow::bail!("The {} character class is empty", name);
+        }
+        ret.push(CharClass {
+            name,
+            chars,
+            min: min.max(1) as usize,
+        });
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("upper won't be empty"))
+    if ret.is_empty() {
+        anyhow::bail!("At least one character class must be enabled");
     }
+    Ok(ret)
+}
 
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("upper won't be empty"))
+/// Expand a charset like "a-zA-Z0-9_", a '-' at either end is taken literally.
+/// Without `ranges` every char is taken literally.
+pub(super) fn parse_charset(spec: &str, ranges: bool) -> anyhow::Result<Vec<u8>> {
+    if let Some(c) = spec.chars().find(|c| !c.is_ascii_graphic()) {
+        anyhow::bail!(
+            "Invalid char {:?} in charset, only printable ASCII is allowed",
+            c
+        );
     }
+    let bytes = spec.as_bytes();
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if ranges && i + 2 < bytes.len() && bytes[i + 1] == b'-' {
+            let (start, end) = (bytes[i], bytes[i + 2]);
+            if start > end {
+                anyhow::bail!("Invalid range {}-{} in charset", start as char, end as char);
+            }
+            ret.extend(start..=end);
+            i += 3;
+        } else {
+            ret.push(bytes[i]);
+            i += 1;
+        }
+    }
+    // duplicates would skew the distribution
+    ret.sort_unstable();
+    ret.dedup();
+    Ok(ret)
+}
 
-    for _ in 0..(length - password.len() as u8) {
-        // let idx = rng.gen_range(0..chars.len());
-        // password.push(chars[idx] as char);
-        let c = chars
-            .choose(&mut rng)
-            .expect("chars won't be empty in this context");
-        password.push(*c);
+fn gen_random(
+    length: usize,
+    classes: &[CharClass],
+    no_repeat: bool,
+    rng: &mut OsRng,
+) -> anyhow::Result<Vec<u8>> {
+    // make sure the password has the required chars of each enabled class, the
+    // other slots draw from all of them
+    let mut slots = classes
+        .iter()
+        .flat_map(|class| std::iter::repeat_n(Some(class), class.min))
+        .collect::<Vec<_>>();
+    slots.resize(length, None);
+    let chars = classes
+        .iter()
+        .flat_map(|class| class.chars.iter().copied())
+        .collect::<Vec<_>>();
+    fill_slots(&mut slots, &chars, no_repeat, rng)
+}
+
+/// Shuffle the slots and draw one char per slot, from its class or else from
+/// `pool`. With `no_repeat` a layout no password fits is shuffled again, and each
+/// draw leaves out the char before it and any char that leaves the rest unfillable.
+fn fill_slots(
+    slots: &mut [Option<&CharClass>],
+    pool: &[u8],
+    no_repeat: bool,
+    rng: &mut OsRng,
+) -> anyhow::Result<Vec<u8>> {
+    for _ in 0..MAX_ATTEMPTS {
+        slots.shuffle(rng);
+        let Some(choices) = slot_choices(slots, pool, no_repeat) else {
+            continue;
+        };
+        let mut password = Vec::with_capacity(slots.len());
+        for choice in &choices {
+            let prev = password.last().copied();
+            let allowed = choice
+                .iter()
+                .copied()
+                .filter(|&c| !no_repeat || Some(c) != prev)
+                .collect::<Vec<_>>();
+            password.push(*allowed.choose(rng).expect("a choice is left"));
+        }
+        return Ok(password);
     }
-    // TODO: make sure the password has at least one of each type
-    password.shuffle(&mut rng);
+    anyhow::bail!("Can't avoid repeated chars, the character set is too small")
+}
+
+/// The chars each slot may take so that the slots after it can still be filled,
+/// `None` if some slot has none. Without repeats a slot can't take the only
+/// char the next slot allows.
+fn slot_choices(
+    slots: &[Option<&CharClass>],
+    pool: &[u8],
+    no_repeat: bool,
+) -> Option<Vec<Vec<u8>>> {
+    let mut ret: Vec<Vec<u8>> = vec![Vec::new(); slots.len()];
+    for i in (0..slots.len()).rev() {
+        let chars = slots[i].map_or(pool, |class| &class.chars);
+        let taken = match ret.get(i + 1) {
+            Some(next) if no_repeat && next.len() == 1 => Some(next[0]),
+            _ => None,
+        };
+        ret[i] = chars
+            .iter()
+            .copied()
+            .filter(|&c| Some(c) != taken)
+            .collect();
+        if ret[i].is_empty() {
+            return None;
+        }
+    }
+    Some(ret)
+}
+
+/// Entropy in bits of the passwords generated from `config`. The required chars
+/// are counted as if they came from the whole pool, so this is a slight overestimate.
+pub fn gen_pass_entropy(config: &GenPassConfig) -> anyhow::Result<f64> {
+    let classes = char_classes(config)?;
+    let length = config.length as usize;
+    if !config.pronounceable {
+        let pool = classes.iter().map(|c| c.chars.len()).sum::<usize>() as f64;
+        // with no_repeat every char after the first has one choice less
+        let entropy = match config.no_repeat {
+            true => pool.log2() + (length - 1) as f64 * (pool - 1.0).log2(),
+            false => length as f64 * pool.log2(),
+        };
+        return Ok(entropy);
+    }
+    // the choice of the uppercase positions is left out
+    let (consonants, vowels) = pronounceable_letters(&classes)?;
+    let tail = classes.iter().filter(|c| c.is_tail());
+    let letters = length - tail.clone().map(|c| c.min).sum::<usize>();
+    let entropy = tail
+        .map(|c| c.min as f64 * (c.chars.len() as f64).log2())
+        .sum::<f64>()
+        + letters.div_ceil(2) as f64 * (consonants.len() as f64).log2()
+        + (letters / 2) as f64 * (vowels.len() as f64).log2();
+    Ok(entropy)
+}
+
+/// The consonants and vowels left by the letter classes, in lowercase
+fn pronounceable_letters(classes: &[CharClass]) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let find = |name: &str| classes.iter().find(|class| class.name == name);
+    // letters are picked in lowercase and then cased to fit the enabled classes
+    let Some(letters) = find("lowercase").or(find("uppercase")) else {
+        anyhow::bail!("A pronounceable password needs letters");
+    };
+    let allowed = |set: &[u8]| -> Vec<u8> {
+        set.iter()
+            .copied()
+            .filter(|c| {
+                letters.chars.contains(&c.to_ascii_uppercase()) || letters.chars.contains(c)
+            })
+            .collect()
+    };
+    let (consonants, vowels) = (allowed(CONSONANTS), allowed(VOWELS));
+    if consonants.is_empty() || vowels.is_empty() {
+        anyhow::bail!("Not enough consonants and vowels left for a pronounceable password");
+    }
+    Ok((consonants, vowels))
+}
+
+/// Consonant/vowel syllables, with the required digits and symbols appended
+fn gen_pronounceable(
+    length: usize,
+    classes: &[CharClass],
+    no_repeat: bool,
+    rng: &mut OsRng,
+) -> anyhow::Result<Vec<u8>> {
+    let find = |name: &str| classes.iter().find(|class| class.name == name);
+    let (upper, lower) = (find("uppercase"), find("lowercase"));
+    let (consonants, vowels) = pronounceable_letters(classes)?;
 
-    // output password strength in stderr
-    let password = String::from_utf8(password)?;
-    // let estimate = zxcvbn(&password, &[])?;
+    // consonants and vowels alternate, so only the tail can repeat a char
+    let mut slots = classes
+        .iter()
+        .filter(|c| c.is_tail())
+        .flat_map(|class| std::iter::repeat_n(Some(class), class.min))
+        .collect::<Vec<_>>();
+    let tail = fill_slots(&mut slots, &[], no_repeat, rng)?;
 
+    let mut password = (0..length - tail.len())
+        .map(|i| {
+            let set = if i % 2 == 0 { &consonants } else { &vowels };
+            *set.choose(rng).expect("set won't be empty")
+        })
+        .collect::<Vec<_>>();
+    match (upper, lower) {
+        (Some(upper), Some(_)) => {
+            let mut positions = (0..password.len())
+                .filter(|&i| upper.chars.contains(&password[i].to_ascii_uppercase()))
+                .collect::<Vec<_>>();
+            if positions.len() < upper.min {
+                anyhow::bail!("Not enough letters for {} uppercase chars", upper.min);
+            }
+            positions.shuffle(rng);
+            for &i in &positions[..upper.min] {
+                password[i].make_ascii_uppercase();
+            }
+        }
+        (Some(_), None) => password.make_ascii_uppercase(),
+        _ => {}
+    }
+    password.extend(tail);
     Ok(password)
 }
+
+/// `len` bytes from the same OS randomness the passwords come from
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    OsRng.fill(&mut buf[..]);
+    buf
+}
+
+/// Generate `count` unique passwords with `generate`, which returns a password
+/// and its entropy. Large counts are generated and scored in parallel.
+pub fn process_gen_pass_bulk(
+    count: usize,
+    generate: impl Fn() -> anyhow::Result<(String, f64)> + Sync,
+) -> anyhow::Result<Vec<PassRecord>> {
+    let mut seen = HashSet::new();
+    let mut passwords = Vec::new();
+    // rounds in a row that only produced duplicates
+    let mut stalled = 0;
+    while passwords.len() < count {
+        let missing = count - passwords.len();
+        let batch = if missing < PARALLEL_THRESHOLD {
+            (0..missing)
+                .map(|_| generate())
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            (0..missing)
+                .into_par_iter()
+                .map(|_| generate())
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        let before = passwords.len();
+        for (password, entropy) in batch {
+            // zxcvbn refuses to score a blank password
+            if password.is_empty() {
+                anyhow::bail!("The settings produced an empty password");
+            }
+            if seen.insert(password.clone()) {
+                passwords.push((password, entropy));
+            }
+        }
+        stalled = if passwords.len() == before {
+            stalled + 1
+        } else {
+            0
+        };
+        if stalled == MAX_ATTEMPTS {
+            anyhow::bail!(
+                "Can't generate {} unique passwords, the settings allow too few",
+                count
+            );
+        }
+    }
+
+    passwords
+        .into_par_iter()
+        .with_min_len(PARALLEL_THRESHOLD)
+        .map(|(password, entropy_bits)| {
+            let score = zxcvbn(&password, &[])?.score();
+            Ok(PassRecord {
+                password,
+                score,
+                entropy_bits,
+            })
+        })
+        .collect()
+}
+
+pub fn render_pass_records(
+    records: &[PassRecord],
+    format: GenPassFormat,
+) -> anyhow::Result<String> {
+    match format {
+        GenPassFormat::Plain => Ok(records
+            .iter()
+            .map(|r| format!("{}\n", r.password))
+            .collect()),
+        GenPassFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for record in records {
+                writer.serialize(record)?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        GenPassFormat::Json => Ok(serialize_output(&records, OutputFormat::Json)? + "\n"),
+        GenPassFormat::Yaml => serialize_output(&records, OutputFormat::Yaml),
+    }
+}
+
+/// The distinct words of a wordlist file, or of the embedded list. The file has one
+/// word per line, EFF style "11111<TAB>word" lines are accepted as well.
+pub fn load_wordlist(wordlist: Option<&str>) -> anyhow::Result<Vec<String>> {
+    let content = match wordlist {
+        Some(path) => fs::read_to_string(path)?,
+        None => WORDLIST.to_string(),
+    };
+    let mut list = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    list.sort_unstable();
+    list.dedup();
+    if list.len() < 2 {
+        anyhow::bail!("The wordlist needs at least 2 distinct words");
+    }
+    Ok(list)
+}
+
+/// Generate a passphrase of random words from a `load_wordlist` list, returns it
+/// with its entropy in bits
+pub fn process_gen_passphrase(
+    words: u8,
+    separator: &str,
+    capitalize: bool,
+    add_number: bool,
+    list: &[String],
+) -> anyhow::Result<(String, f64)> {
+    if words == 0 {
+        anyhow::bail!("A passphrase needs at least one word");
+    }
+    if list.len() < 2 {
+        anyhow::bail!("The wordlist needs at least 2 distinct words");
+    }
+
+    let mut rng = OsRng;
+    let mut chosen = Vec::with_capacity(words as usize);
+    for _ in 0..words {
+        let word = list.choose(&mut rng).expect("list won't be empty");
+        let word = if capitalize {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        } else {
+            word.to_string()
+        };
+        chosen.push(word);
+    }
+    let mut entropy = words as f64 * (list.len() as f64).log2();
+    if add_number {
+        // one digit appended to one of the words
+        let idx = rng.gen_range(0..chosen.len());
+        chosen[idx].push(char::from(b'0' + rng.gen_range(0..10)));
+        entropy += (10.0 * words as f64).log2();
+    }
+
+    Ok((chosen.join(separator), entropy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn count(password: &str, class: &[u8]) -> usize {
+        password.bytes().filter(|b| class.contains(b)).count()
+    }
+
+    proptest! {
+        #[test]
+        fn test_gen_pass_class_coverage(
+            length in 4u8..=64,
+            (upper, lower, number, symbol) in any::<(bool, bool, bool, bool)>(),
+            (min_upper, min_digits, min_symbols) in (0u8..4, 0u8..4, 0u8..4),
+        ) {
+            let config = GenPassConfig {
+                length,
+                upper,
+                lower,
+                number,
+                symbol,
+                min_upper: if upper { min_upper } else { 0 },
+                min_digits: if number { min_digits } else { 0 },
+                min_symbols: if symbol { min_symbols } else { 0 },
+                ..Default::default()
+            };
+            let required = [
+                (upper, config.min_upper),
+                (lower, 0),
+                (number, config.min_digits),
+                (symbol, config.min_symbols),
+            ]
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, min)| (*min).max(1) as usize)
+            .sum::<usize>();
+            let ret = process_gen_pass(&config);
+            if required == 0 || required > length as usize {
+                prop_assert!(ret.is_err());
+                return std::result::Result::Ok(());
+            }
+            let password = ret.unwrap();
+            prop_assert_eq!(password.len(), length as usize);
+            for (enabled, class, min) in [
+                (upper, UPPER, config.min_upper),
+                (lower, LOWER, 0),
+                (number, NUMBER, config.min_digits),
+                (symbol, SYMBOL, config.min_symbols),
+            ] {
+                let n = count(&password, class);
+                if enabled {
+                    prop_assert!(n >= min.max(1) as usize);
+                } else {
+                    prop_assert_eq!(n, 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gen_pass_invalid_config() {
+        let config = GenPassConfig {
+            length: 0,
+            ..Default::default()
+        };
+        assert!(process_gen_pass(&config).is_err());
+        let config = GenPassConfig {
+            upper: false,
+            lower: false,
+            number: false,
+            symbol: false,
+            ..Default::default()
+        };
+        assert!(process_gen_pass(&config).is_err());
+        let config = GenPassConfig {
+            symbol: false,
+            min_symbols: 2,
+            ..Default::default()
+        };
+        assert!(process_gen_pass(&config).is_err());
+    }
+
+    #[test]
+    fn test_gen_pass_charset_rules() -> anyhow::Result<()> {
+        assert_eq!(parse_charset("a-c0-2-", true)?, b"-012abc");
+        assert_eq!(parse_charset("#-!", false)?, b"!#-");
+        assert!(parse_charset("z-a", true).is_err());
+
+        let config = GenPassConfig {
+            length: 32,
+            charset: Some("a-f0-9".into()),
+            exclude: "abc".into(),
+            no_repeat: true,
+            ..Default::default()
+        };
+        let password = process_gen_pass(&config)?;
+        assert!(password.bytes().all(|c| b"def0123456789".contains(&c)));
+        assert!(password.as_bytes().windows(2).all(|w| w[0] != w[1]));
+
+        let config = GenPassConfig {
+            symbols: Some("-_".into()),
+            no_ambiguous: true,
+            ..Default::default()
+        };
+        let password = process_gen_pass(&config)?;
+        assert!(password
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+        assert!(!password.bytes().any(|c| AMBIGUOUS.contains(&c)));
+
+        // the lookalikes are opt-in
+        let password = process_gen_pass(&GenPassConfig {
+            length: 64,
+            ..Default::default()
+        })?;
+        assert!(!password.bytes().any(|c| AMBIGUOUS.contains(&c)));
+        assert_eq!(builtin_class(NUMBER, true), b"0123456789");
+        assert_eq!(builtin_class(&[UPPER, LOWER].concat(), true).len(), 52);
+
+        let config = GenPassConfig {
+            length: 32,
+            charset: Some("ab".into()),
+            no_repeat: true,
+            ..Default::default()
+        };
+        let password = process_gen_pass(&config)?;
+        assert!(password == "ab".repeat(16) || password == "ba".repeat(16));
+        assert_eq!(gen_pass_entropy(&config)?, 1.0);
+        let config = GenPassConfig {
+            charset: Some("a".into()),
+            ..config
+        };
+        assert!(process_gen_pass(&config).is_err());
+        // only "aBaB" and "BaBa" fit, every run has to find them
+        let config = GenPassConfig {
+            length: 4,
+            charset: Some("aB".into()),
+            ..config
+        };
+        for _ in 0..50 {
+            let password = process_gen_pass(&config)?;
+            assert!(password == "aBaB" || password == "BaBa");
+        }
+        // a one-char class can still be used more than once
+        let config = GenPassConfig {
+            length: 5,
+            charset: Some("ab_".into()),
+            min_symbols: 3,
+            ..config
+        };
+        assert!(process_gen_pass(&config)?.starts_with('_'));
+        let config = GenPassConfig {
+            min_symbols: 4,
+            ..config
+        };
+        assert!(process_gen_pass(&config).is_err());
+
+        let config = GenPassConfig {
+            length: 12,
+            min_digits: 2,
+            pronounceable: true,
+            ..Default::default()
+        };
+        let password = process_gen_pass(&config)?;
+        let letters = password.trim_end_matches(|c: char| !c.is_ascii_alphabetic());
+        assert!(letters.len() <= 9);
+        assert!(letters
+            .to_ascii_lowercase()
+            .bytes()
+            .step_by(2)
+            .all(|c| CONSONANTS.contains(&c)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gen_pass_bulk() -> anyhow::Result<()> {
+        // only 8 passwords exist for this config
+        let config = GenPassConfig {
+            length: 3,
+            charset: Some("ab".into()),
+            ..Default::default()
+        };
+        let entropy = gen_pass_entropy(&config)?;
+        assert_eq!(entropy, 3.0);
+        let generate = || Ok((process_gen_pass(&config)?, entropy));
+        let records = process_gen_pass_bulk(8, generate)?;
+        let unique = records.iter().map(|r| &r.password).collect::<HashSet<_>>();
+        assert_eq!(unique.len(), 8);
+        assert!(process_gen_pass_bulk(9, generate).is_err());
+
+        let csv = render_pass_records(&records[..1], GenPassFormat::Csv)?;
+        assert!(csv.starts_with("password,score,entropy_bits\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gen_passphrase() -> anyhow::Result<()> {
+        let list = load_wordlist(None)?;
+        let (pass, entropy) = process_gen_passphrase(6, " ", false, false, &list)?;
+        assert_eq!(pass.split(' ').count(), 6);
+        // 2048 words carry 11 bits each
+        assert_eq!(entropy, 66.0);
+        assert!(process_gen_passphrase(0, " ", false, false, &list).is_err());
+        Ok(())
+    }
+}
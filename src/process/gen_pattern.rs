@@ -0,0 +1,199 @@
+use std::str::Chars;
+
+use anyhow::{Ok, Result};
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::gen_pass::{builtin_class, parse_charset, AMBIGUOUS, LOWER, NUMBER, SYMBOL, UPPER};
+
+/// the longest repeat a pattern may ask for
+const MAX_REPEAT: usize = 1024;
+
+/// One char drawn from `chars`, `min..=max` times
+#[derive(Debug)]
+struct Atom {
+    chars: Vec<char>,
+    min: usize,
+    max: usize,
+}
+
+/// Generate a secret from a pattern such as `[A-Z]{3}\d{4}`, returns it with
+/// its entropy in bits.
+///
+/// - `[...]` is a set in the `--charset` syntax, e.g. `[a-f0-9]`
+/// - `\d` digit, `\u` uppercase, `\l` lowercase, `\a` letter, `\w` letter or
+///   digit, `\s` symbol, `\h` and `\H` lower and upper case hex digit
+/// - `{n}` or `{m,n}` repeats the previous char or set
+/// - anything else, or any other escaped char, is a literal
+///
+/// `exclude` and `no_ambiguous` filter the sets and classes, never the literals.
+/// The classes leave out the lookalikes unless `lookalikes` is set.
+pub fn process_gen_pattern(
+    pattern: &str,
+    exclude: &str,
+    no_ambiguous: bool,
+    lookalikes: bool,
+) -> Result<(String, f64)> {
+    let atoms = parse_pattern(pattern, exclude, no_ambiguous, lookalikes)?;
+    let mut rng = OsRng;
+    // parse_pattern makes sure some atom can draw a char, so redrawing the
+    // lengths keeps the secret from being empty
+    let counts = loop {
+        let counts = atoms
+            .iter()
+            .map(|atom| rng.gen_range(atom.min..=atom.max))
+            .collect::<Vec<_>>();
+        if counts.iter().any(|&n| n > 0) {
+            break counts;
+        }
+    };
+    let mut ret = String::new();
+    let mut entropy = 0.0;
+    for (atom, count) in atoms.iter().zip(counts) {
+        for _ in 0..count {
+            ret.push(*atom.chars.choose(&mut rng).expect("atom won't be empty"));
+        }
+        // given the lengths the chars are uniform, so this is exact for fixed
+        // repeats. The length choices are left out, different lengths across
+        // atoms can give the same secret, which makes this a lower bound.
+        let mean = (atom.min + atom.max) as f64 / 2.0;
+        entropy += mean * (atom.chars.len() as f64).log2();
+    }
+    Ok((ret, entropy))
+}
+
+fn parse_pattern(
+    pattern: &str,
+    exclude: &str,
+    no_ambiguous: bool,
+    lookalikes: bool,
+) -> Result<Vec<Atom>> {
+    let builtin = |chars: &[u8]| builtin_class(chars, lookalikes);
+    let class = |chars: &[u8]| -> Result<Vec<char>> {
+        let ret = chars
+            .iter()
+            .filter(|c| !exclude.as_bytes().contains(c))
+            .filter(|c| !(no_ambiguous && AMBIGUOUS.contains(c)))
+            .map(|&c| c as char)
+            .collect::<Vec<_>>();
+        if ret.is_empty() {
+            anyhow::bail!("A set of the pattern is empty after the exclusions");
+        }
+        Ok(ret)
+    };
+
+    let mut atoms: Vec<Atom> = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        let set = match c {
+            '\\' => match chars.next() {
+                Some('d') => class(&builtin(NUMBER))?,
+                Some('u') => class(&builtin(UPPER))?,
+                Some('l') => class(&builtin(LOWER))?,
+                Some('a') => class(&builtin(&[UPPER, LOWER].concat()))?,
+                Some('w') => class(&builtin(&[UPPER, LOWER, NUMBER].concat()))?,
+                Some('s') => class(SYMBOL)?,
+                Some('h') => class(b"0123456789abcdef")?,
+                Some('H') => class(b"0123456789ABCDEF")?,
+                Some(c) => vec![c],
+                None => anyhow::bail!("The pattern ends with a lone '\\'"),
+            },
+            '[' => {
+                let spec = take_until(&mut chars, ']')?;
+                class(&parse_charset(&spec, true)?)?
+            }
+            '{' => {
+                let (min, max) = parse_repeat(&mut chars)?;
+                match atoms.last_mut() {
+                    Some(atom) if atom.min == 1 && atom.max == 1 => {
+                        atom.min = min;
+                        atom.max = max;
+                    }
+                    _ => anyhow::bail!("A repeat must follow a char or a set"),
+                }
+                continue;
+            }
+            c => vec![c],
+        };
+        atoms.push(Atom {
+            chars: set,
+            min: 1,
+            max: 1,
+        });
+    }
+    if atoms.iter().all(|atom| atom.max == 0) {
+        anyhow::bail!("The pattern is empty or only repeats things zero times");
+    }
+    Ok(atoms)
+}
+
+/// `n}` or `m,n}`, the `{` is already consumed
+fn parse_repeat(chars: &mut Chars) -> Result<(usize, usize)> {
+    let spec = take_until(chars, '}')?;
+    let parse = |s: &str| -> Result<usize> {
+        s.trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid repeat {{{}}} in the pattern", spec))
+    };
+    let (min, max) = match spec.split_once(',') {
+        Some((min, max)) => (parse(min)?, parse(max)?),
+        None => (parse(&spec)?, parse(&spec)?),
+    };
+    if min > max || max > MAX_REPEAT {
+        anyhow::bail!(
+            "Invalid repeat {{{}}}, it must be a range within 0..={}",
+            spec,
+            MAX_REPEAT
+        );
+    }
+    Ok((min, max))
+}
+
+fn take_until(chars: &mut Chars, end: char) -> Result<String> {
+    let mut ret = String::new();
+    for c in chars.by_ref() {
+        if c == end {
+            return Ok(ret);
+        }
+        ret.push(c);
+    }
+    anyhow::bail!("Missing '{}' in the pattern", end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gen_pattern() -> Result<()> {
+        let (ret, entropy) = process_gen_pattern(r"[A-Z]{3}\d{4}", "", false, true)?;
+        assert_eq!(ret.len(), 7);
+        assert!(ret[..3].bytes().all(|c| c.is_ascii_uppercase()));
+        assert!(ret[3..].bytes().all(|c| c.is_ascii_digit()));
+        assert!((entropy - (3.0 * 26f64.log2() + 4.0 * 10f64.log2())).abs() < 1e-9);
+
+        let (ret, entropy) = process_gen_pattern(r"\h{8}-4\h{3}-[89ab]\h{3}", "", false, false)?;
+        assert_eq!(&ret[8..10], "-4");
+        assert_eq!(entropy, 8.0 * 4.0 + 3.0 * 4.0 + 2.0 + 3.0 * 4.0);
+
+        let (ret, entropy) = process_gen_pattern(r"x{0,3}\{", "", false, false)?;
+        assert!(ret.ends_with('{') && ret.len() <= 4);
+        assert_eq!(entropy, 0.0);
+        // the lengths add no bits, "a" may come from either atom
+        let (_, entropy) = process_gen_pattern("[ab]{0,1}[ab]{0,1}", "", false, false)?;
+        assert_eq!(entropy, 1.0);
+
+        assert!(process_gen_pattern(r"\d", "0123456789", false, false).is_err());
+        for _ in 0..20 {
+            let (ret, _) = process_gen_pattern("[a-z]{0,1}", "", false, false)?;
+            assert_eq!(ret.len(), 1);
+        }
+        assert!(process_gen_pattern(r"\d{0}", "", false, false).is_err());
+        assert!(process_gen_pattern("a{0}b{0,0}", "", false, false).is_err());
+        assert!(process_gen_pattern("{2}", "", false, false).is_err());
+        assert!(process_gen_pattern("a{3,1}", "", false, false).is_err());
+        assert!(process_gen_pattern("[a-z", "", false, false).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Ok, Result};
+use ulid::Ulid;
+use uuid::Builder;
+
+use super::gen_pass::random_bytes;
+use crate::{process_codec_encode, process_encode, Base64Format, CodecFormat, TokenEncoding};
+
+/// `count` tokens of `bytes` random bytes each
+pub fn process_gen_token(
+    bytes: usize,
+    encoding: TokenEncoding,
+    count: usize,
+) -> Result<Vec<String>> {
+    (0..count)
+        .map(|_| {
+            let mut reader = &random_bytes(bytes)[..];
+            let mut buf = Vec::new();
+            match encoding {
+                TokenEncoding::Base64Url => {
+                    process_encode(&mut reader, &mut buf, Base64Format::UrlSafe, Some(0))?
+                }
+                TokenEncoding::Hex => {
+                    process_codec_encode(&mut reader, &mut buf, CodecFormat::Hex)?
+                }
+                TokenEncoding::Base58 => {
+                    process_codec_encode(&mut reader, &mut buf, CodecFormat::Base58)?
+                }
+            }
+            Ok(String::from_utf8(buf)?)
+        })
+        .collect()
+}
+
+/// Random v4 UUIDs, or time ordered v7 ones. A batch of v7 UUIDs is sorted, so
+/// ids created in the same millisecond keep their order.
+pub fn process_gen_uuid(v7: bool, count: usize) -> Vec<String> {
+    let mut ret = (0..count)
+        .map(|_| {
+            let bytes = random_bytes(16);
+            let builder = if v7 {
+                let rand = bytes[..10].try_into().expect("10 bytes");
+                Builder::from_unix_timestamp_millis(unix_now_millis(), &rand)
+            } else {
+                Builder::from_random_bytes(bytes.try_into().expect("16 bytes"))
+            };
+            builder.into_uuid()
+        })
+        .collect::<Vec<_>>();
+    if v7 {
+        ret.sort();
+    }
+    ret.iter().map(|id| id.to_string()).collect()
+}
+
+/// ULIDs, a batch is sorted like the v7 UUIDs
+pub fn process_gen_ulid(count: usize) -> Vec<String> {
+    let mut ret = (0..count)
+        .map(|_| {
+            let rand = u128::from_be_bytes(random_bytes(16).try_into().expect("16 bytes"));
+            Ulid::from_parts(unix_now_millis(), rand)
+        })
+        .collect::<Vec<_>>();
+    ret.sort();
+    ret.iter().map(|id| id.to_string()).collect()
+}
+
+fn unix_now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gen_token_ids() -> Result<()> {
+        let tokens = process_gen_token(32, TokenEncoding::Base64Url, 2)?;
+        assert_eq!(tokens[0].len(), 43);
+        assert_ne!(tokens[0], tokens[1]);
+        assert_eq!(process_gen_token(16, TokenEncoding::Hex, 1)?[0].len(), 32);
+
+        let ids = process_gen_uuid(true, 10);
+        assert!(ids.iter().all(|id| id.as_bytes()[14] == b'7'));
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        let id = uuid::Uuid::parse_str(&process_gen_uuid(false, 1)[0])?;
+        assert_eq!(id.get_version_num(), 4);
+
+        let ids = process_gen_ulid(10);
+        assert!(ids.iter().all(|id| id.len() == 26));
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        Ok(())
+    }
+}
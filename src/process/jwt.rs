@@ -0,0 +1,376 @@
+use std::{
+    fs,
+    io::Read,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Ok, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use super::text::{Ed2519Signer, Ed2519Verify, KeyLoader, TextSign, TextVerify};
+use crate::{get_reader, JwtAlg};
+
+/// HMAC-SHA256 with a shared secret, the secret is the raw content of the key file
+pub struct Hs256 {
+    key: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct JwtValidation {
+    /// seconds of clock skew tolerated for `exp`, `nbf` and `iat`
+    pub leeway: u64,
+    pub audience: Option<String>,
+    pub issuer: Option<String>,
+}
+
+impl TextSign for Hs256 {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key)?;
+        mac.update(&buf);
+        Ok(mac.finalize().into_bytes().to_vec())
+    }
+}
+
+impl TextVerify for Hs256 {
+    fn verify(&self, mut reader: impl Read, sign: &[u8]) -> Result<bool> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key)?;
+        mac.update(&buf);
+        // constant time comparison
+        Ok(mac.verify_slice(sign).is_ok())
+    }
+}
+
+impl KeyLoader for Hs256 {
+    fn load(path: impl AsRef<Path>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let key = fs::read(path)?;
+        if key.is_empty() {
+            anyhow::bail!("HS256 key is empty");
+        }
+        Ok(Self { key })
+    }
+}
+
+/// Sign the json claims from the input. With `expires_in`, `iat` and `exp` are set from now.
+pub fn process_jwt_sign(
+    input: &str,
+    key: &str,
+    alg: JwtAlg,
+    expires_in: Option<u64>,
+) -> Result<String> {
+    let mut reader = get_reader(input)?;
+    let mut claims: Value = serde_json::from_reader(&mut reader)?;
+    let Some(map) = claims.as_object_mut() else {
+        anyhow::bail!("JWT claims must be a json object");
+    };
+    if let Some(expires_in) = expires_in {
+        let now = now();
+        map.insert("iat".into(), json!(now));
+        let exp = i64::try_from(expires_in)
+            .ok()
+            .and_then(|secs| now.checked_add(secs))
+            .ok_or_else(|| anyhow::anyhow!("Expiration {}s is too far away", expires_in))?;
+        map.insert("exp".into(), json!(exp));
+    }
+
+    let header = json!({ "alg": alg.to_string(), "typ": "JWT" });
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?)
+    );
+    let mut reader = signing_input.as_bytes();
+    let sig = match alg {
+        JwtAlg::EdDSA => Ed2519Signer::load(key)?.sign(&mut reader)?,
+        JwtAlg::HS256 => Hs256::load(key)?.sign(&mut reader)?,
+    };
+    Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(sig)))
+}
+
+/// Decode a token without verifying it, and describe its time claims relative to now
+pub fn process_jwt_decode(input: &str) -> Result<String> {
+    let token = read_token(input)?;
+    let (header, claims, _) = split_token(&token)?;
+    let mut ret = format!(
+        "Header:\n{}\nClaims:\n{}\n",
+        serde_json::to_string_pretty(&header)?,
+        serde_json::to_string_pretty(&claims)?
+    );
+    let now = now();
+    for name in ["iat", "nbf", "exp"] {
+        if let Some(value) = claims.get(name) {
+            let Some(time) = numeric_date(value) else {
+                ret.push_str(&format!("{}: {} (not a numeric date)\n", name, value));
+                continue;
+            };
+            // the claims are untrusted, any i64 is possible
+            let (ago, ahead) = (now.saturating_sub(time), time.saturating_sub(now));
+            let note = match (name, time <= now) {
+                ("exp", true) => format!("expired {} ago", human_duration(ago)),
+                ("exp", false) => format!("expires in {}", human_duration(ahead)),
+                ("nbf", true) => format!("valid since {} ago", human_duration(ago)),
+                ("nbf", false) => format!("not valid for another {}", human_duration(ahead)),
+                (_, true) => format!("issued {} ago", human_duration(ago)),
+                (_, false) => format!("issued {} in the future", human_duration(ahead)),
+            };
+            ret.push_str(&format!("{}: {} ({})\n", name, value, note));
+        }
+    }
+    ret.push_str("Signature is NOT verified, use `rcli jwt verify` for that\n");
+    Ok(ret)
+}
+
+/// Verify the signature and the registered claims, and return the claims
+pub fn process_jwt_verify(
+    input: &str,
+    key: &str,
+    alg: JwtAlg,
+    validation: &JwtValidation,
+) -> Result<Value> {
+    let token = read_token(input)?;
+    let (header, claims, sig) = split_token(&token)?;
+    // never let the token choose the algorithm
+    if header.get("alg").and_then(Value::as_str) != Some(&alg.to_string()) {
+        anyhow::bail!(
+            "Token algorithm {} does not match the expected {}",
+            header.get("alg").unwrap_or(&Value::Null),
+            alg
+        );
+    }
+    let signing_input = &token[..token.rfind('.').unwrap_or_default()];
+    let reader = signing_input.as_bytes();
+    let verified = match alg {
+        JwtAlg::EdDSA => Ed2519Verify::load(key)?.verify(reader, &sig)?,
+        JwtAlg::HS256 => Hs256::load(key)?.verify(reader, &sig)?,
+    };
+    if !verified {
+        anyhow::bail!("Invalid token signature");
+    }
+
+    let now = now();
+    // the claims are untrusted and the leeway may be huge, so saturate rather
+    // than overflow
+    let leeway = i64::try_from(validation.leeway).unwrap_or(i64::MAX);
+    let time_claim = |name: &str| -> Result<Option<i64>> {
+        match claims.get(name) {
+            None => Ok(None),
+            Some(value) => match numeric_date(value) {
+                Some(time) => Ok(Some(time)),
+                None => anyhow::bail!("Claim {} is not a numeric date", name),
+            },
+        }
+    };
+    if let Some(exp) = time_claim("exp")? {
+        if now > exp.saturating_add(leeway) {
+            anyhow::bail!(
+                "Token expired {} ago",
+                human_duration(now.saturating_sub(exp))
+            );
+        }
+    }
+    if let Some(nbf) = time_claim("nbf")? {
+        if now.saturating_add(leeway) < nbf {
+            anyhow::bail!(
+                "Token is not valid for another {}",
+                human_duration(nbf.saturating_sub(now))
+            );
+        }
+    }
+    if let Some(iat) = time_claim("iat")? {
+        if now.saturating_add(leeway) < iat {
+            anyhow::bail!(
+                "Token is issued {} in the future",
+                human_duration(iat.saturating_sub(now))
+            );
+        }
+    }
+    if let Some(audience) = &validation.audience {
+        let matched = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !matched {
+            anyhow::bail!("Token audience does not contain {}", audience);
+        }
+    }
+    if let Some(issuer) = &validation.issuer {
+        if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+            anyhow::bail!("Token issuer is not {}", issuer);
+        }
+    }
+    Ok(claims)
+}
+
+fn read_token(input: &str) -> Result<String> {
+    let mut reader = get_reader(input)?;
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf)?;
+    Ok(buf.trim().to_string())
+}
+
+fn split_token(token: &str) -> Result<(Value, Value, Vec<u8>)> {
+    let parts = token.split('.').collect::<Vec<_>>();
+    let [header, claims, sig] = parts[..] else {
+        anyhow::bail!("A JWT must have 3 dot separated parts, got {}", parts.len());
+    };
+    let header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header)?)?;
+    let claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?)?;
+    let sig = URL_SAFE_NO_PAD.decode(sig)?;
+    Ok((header, claims, sig))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// A NumericDate may have a fraction (RFC 7519), it is rounded down to seconds
+fn numeric_date(value: &Value) -> Option<i64> {
+    // out of range floats saturate
+    value
+        .as_i64()
+        .or_else(|| value.as_f64().map(|t| t.floor() as i64))
+}
+
+fn human_duration(secs: i64) -> String {
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let mut rest = secs;
+    let parts = units
+        .iter()
+        .filter_map(|&(unit, size)| {
+            let n = rest / size;
+            rest %= size;
+            (n > 0).then(|| format!("{}{}", n, unit))
+        })
+        .take(2)
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "0s".into()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::text::KeyGenerator;
+    use crate::utils::TestDir;
+
+    fn sign_claims(dir: &TestDir, claims: &str, key: &str, alg: JwtAlg) -> Result<String> {
+        let path = dir.join("claims.json");
+        fs::write(&path, claims)?;
+        let token = process_jwt_sign(path.to_str().unwrap(), key, alg, None)?;
+        let input = dir.join("jwt.token");
+        fs::write(&input, token)?;
+        Ok(input.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn test_jwt_sign_verify_hs256() -> Result<()> {
+        let dir = TestDir::new("jwt_hs256");
+        let key = dir.join("hs256.key");
+        let claims = dir.join("claims.json");
+        fs::write(&key, "secret")?;
+        fs::write(&claims, r#"{"sub":"rcli","aud":["a","b"],"iss":"me"}"#)?;
+        let (claims, key) = (claims.to_str().unwrap(), key.to_str().unwrap());
+        let token = process_jwt_sign(claims, key, JwtAlg::HS256, Some(60))?;
+        let input = dir.join("jwt.token");
+        fs::write(&input, &token)?;
+        let input = input.to_str().unwrap();
+        // too far away to be a timestamp
+        assert!(process_jwt_sign(claims, key, JwtAlg::HS256, Some(u64::MAX)).is_err());
+
+        let validation = JwtValidation {
+            audience: Some("b".into()),
+            issuer: Some("me".into()),
+            ..Default::default()
+        };
+        let verified = process_jwt_verify(input, key, JwtAlg::HS256, &validation)?;
+        assert_eq!(verified["sub"], "rcli");
+
+        let validation = JwtValidation {
+            audience: Some("c".into()),
+            ..Default::default()
+        };
+        assert!(process_jwt_verify(input, key, JwtAlg::HS256, &validation).is_err());
+        assert!(process_jwt_verify(input, key, JwtAlg::EdDSA, &Default::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_sign_verify_eddsa() -> Result<()> {
+        let dir = TestDir::new("jwt_eddsa");
+        let keys = Ed2519Signer::generate()?;
+        let (sk, pk) = (dir.join("ed25519.sk"), dir.join("ed25519.pk"));
+        fs::write(&sk, &keys[0])?;
+        fs::write(&pk, &keys[1])?;
+        let input = sign_claims(
+            &dir,
+            r#"{"sub":"rcli","exp":1}"#,
+            sk.to_str().unwrap(),
+            JwtAlg::EdDSA,
+        )?;
+
+        // signature is fine but the token expired long ago
+        let ret = process_jwt_verify(
+            &input,
+            pk.to_str().unwrap(),
+            JwtAlg::EdDSA,
+            &Default::default(),
+        );
+        assert!(ret.unwrap_err().to_string().starts_with("Token expired"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_extreme_times() -> Result<()> {
+        let dir = TestDir::new("jwt_extreme");
+        let key = dir.join("hs256.key");
+        fs::write(&key, "secret")?;
+        let key = key.to_str().unwrap();
+        let huge_leeway = JwtValidation {
+            leeway: u64::MAX,
+            ..Default::default()
+        };
+
+        let claims = format!(r#"{{"exp":{}}}"#, i64::MIN);
+        let input = sign_claims(&dir, &claims, key, JwtAlg::HS256)?;
+        process_jwt_decode(&input)?;
+        let ret = process_jwt_verify(&input, key, JwtAlg::HS256, &Default::default());
+        assert!(ret.unwrap_err().to_string().starts_with("Token expired"));
+
+        let claims = format!(r#"{{"exp":{},"nbf":{}}}"#, i64::MAX, i64::MAX);
+        let input = sign_claims(&dir, &claims, key, JwtAlg::HS256)?;
+        process_jwt_decode(&input)?;
+        assert!(process_jwt_verify(&input, key, JwtAlg::HS256, &Default::default()).is_err());
+        process_jwt_verify(&input, key, JwtAlg::HS256, &huge_leeway)?;
+
+        // NumericDate may have a fraction
+        let input = sign_claims(
+            &dir,
+            r#"{"exp":4102444800.5,"iat":1.5}"#,
+            key,
+            JwtAlg::HS256,
+        )?;
+        process_jwt_verify(&input, key, JwtAlg::HS256, &Default::default())?;
+        assert!(process_jwt_decode(&input)?.contains("iat: 1.5 (issued"));
+        let input = sign_claims(&dir, r#"{"exp":1.5}"#, key, JwtAlg::HS256)?;
+        let ret = process_jwt_verify(&input, key, JwtAlg::HS256, &Default::default());
+        assert!(ret.unwrap_err().to_string().starts_with("Token expired"));
+        Ok(())
+    }
+}
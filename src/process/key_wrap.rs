@@ -0,0 +1,285 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{Ok, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    KeyInit, XChaCha20Poly1305,
+};
+use zeroize::Zeroizing;
+
+use super::gen_pass::random_bytes;
+use crate::write_secret_file;
+
+const ENCRYPTED_KEY_BEGIN: &str = "-----BEGIN RCLI ENCRYPTED KEY-----";
+const ENCRYPTED_KEY_END: &str = "-----END RCLI ENCRYPTED KEY-----";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// version, the three Argon2id params, salt and nonce
+const HEADER_LEN: usize = 1 + 12 + SALT_LEN + NONCE_LEN;
+/// the largest costs a file may ask for, 256 MiB of memory, anything above is
+/// refused rather than run
+const MAX_M_COST: u32 = 1 << 18;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+pub const PASSPHRASE_ENV: &str = "RCLI_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "RCLI_NEW_PASSPHRASE";
+
+/// Argon2id cost, stored in the header so it can be raised later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// memory in KiB
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Refuse costs beyond the built-in limits, the params of a file are untrusted
+    pub fn validate(&self) -> Result<()> {
+        for (name, value, max) in [
+            ("memory cost (KiB)", self.m_cost, MAX_M_COST),
+            ("time cost", self.t_cost, MAX_T_COST),
+            ("parallelism", self.p_cost, MAX_P_COST),
+        ] {
+            if value > max {
+                anyhow::bail!(
+                    "The Argon2id {} {} is larger than the limit {}",
+                    name,
+                    value,
+                    max
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The 32-byte key Argon2id derives from `passphrase`
+pub fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Zeroizing<[u8; 32]>> {
+    params.validate()?;
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2id params: {}", e))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| anyhow::anyhow!("Argon2id failed: {}", e))?;
+    Ok(key)
+}
+
+pub(super) fn is_encrypted_key(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_KEY_BEGIN.as_bytes())
+}
+
+/// Wrap a key file with XChaCha20-Poly1305 under a passphrase derived key.
+///
+/// The container is base64 between a header and a footer line, and holds
+/// `version | m_cost | t_cost | p_cost | salt | nonce | ciphertext`, the
+/// numbers are big endian u32s and everything before the ciphertext is
+/// authenticated.
+pub(super) fn encrypt_key(data: &[u8], passphrase: &str, params: KdfParams) -> Result<Vec<u8>> {
+    let salt = random_bytes(SALT_LEN);
+    let nonce = random_bytes(NONCE_LEN);
+    let mut header = vec![VERSION];
+    for v in [params.m_cost, params.t_cost, params.p_cost] {
+        header.extend_from_slice(&v.to_be_bytes());
+    }
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    let payload = Payload {
+        msg: data,
+        aad: &header,
+    };
+    let ciphertext = cipher
+        .encrypt(nonce.as_slice().into(), payload)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the key"))?;
+    header.extend_from_slice(&ciphertext);
+    let body = STANDARD
+        .encode(header)
+        .as_bytes()
+        .chunks(64)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(format!("{}\n{}\n{}\n", ENCRYPTED_KEY_BEGIN, body, ENCRYPTED_KEY_END).into_bytes())
+}
+
+/// Unwrap an `encrypt_key` container, the result is the original key file
+pub(super) fn decrypt_key(data: &[u8], passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let text = std::str::from_utf8(data)
+        .map_err(|_| anyhow::anyhow!("Invalid encrypted key: not text"))?
+        .trim();
+    let body = text
+        .strip_prefix(ENCRYPTED_KEY_BEGIN)
+        .and_then(|body| body.strip_suffix(ENCRYPTED_KEY_END))
+        .ok_or_else(|| anyhow::anyhow!("Invalid encrypted key: missing the header or footer"))?;
+    let body = body.split_whitespace().collect::<String>();
+    let data = STANDARD
+        .decode(body)
+        .map_err(|e| anyhow::anyhow!("Invalid encrypted key: {}", e))?;
+    if data.len() < HEADER_LEN {
+        anyhow::bail!("Invalid encrypted key: truncated");
+    }
+    if data[0] != VERSION {
+        anyhow::bail!("Unsupported encrypted key version {}", data[0]);
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let num = |i: usize| u32::from_be_bytes(header[1 + i * 4..5 + i * 4].try_into().unwrap());
+    let params = KdfParams {
+        m_cost: num(0),
+        t_cost: num(1),
+        p_cost: num(2),
+    };
+    let salt = &header[13..13 + SALT_LEN];
+    let nonce = &header[13 + SALT_LEN..];
+
+    let key = derive_key(passphrase, salt, params)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    let plain = cipher
+        .decrypt(nonce.into(), payload)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase, or the key file is corrupted"))?;
+    Ok(Zeroizing::new(plain))
+}
+
+/// Read a key file, decrypting it if it is encrypted
+pub(super) fn read_key_file(path: impl AsRef<Path>) -> Result<Zeroizing<Vec<u8>>> {
+    let path = path.as_ref();
+    let data = Zeroizing::new(fs::read(path)?);
+    if !is_encrypted_key(&data) {
+        return Ok(data);
+    }
+    let prompt = format!("Passphrase for {}: ", path.display());
+    let passphrase = read_passphrase(PASSPHRASE_ENV, &prompt, false)?;
+    decrypt_key(&data, &passphrase)
+}
+
+/// The passphrase from the `env` variable, from the file named by `<env>_FILE`,
+/// or else from a terminal prompt, asked twice when `confirm` is set
+pub fn read_passphrase(env: &str, prompt: &str, confirm: bool) -> Result<Zeroizing<String>> {
+    let file_env = format!("{}_FILE", env);
+    let passphrase = if let Result::Ok(passphrase) = env::var(env) {
+        Zeroizing::new(passphrase)
+    } else if let Result::Ok(path) = env::var(&file_env) {
+        let content = Zeroizing::new(
+            fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Cannot read {} {}: {}", file_env, path, e))?,
+        );
+        Zeroizing::new(content.lines().next().unwrap_or_default().to_string())
+    } else {
+        let prompt_password = |prompt: &str| {
+            rpassword::prompt_password(prompt)
+                .map(Zeroizing::new)
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Cannot prompt for the passphrase ({}), set {} or {}",
+                        e,
+                        env,
+                        file_env
+                    )
+                })
+        };
+        let passphrase = prompt_password(prompt)?;
+        if confirm && *prompt_password("Confirm the passphrase: ")? != *passphrase {
+            anyhow::bail!("The passphrases don't match");
+        }
+        passphrase
+    };
+    if confirm && passphrase.is_empty() {
+        anyhow::bail!("The passphrase is empty");
+    }
+    Ok(passphrase)
+}
+
+/// Change the passphrase of a key file in place, a plaintext key gets encrypted
+pub fn process_key_rekey(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let key = read_key_file(path)?;
+    let prompt = format!("New passphrase for {}: ", path.display());
+    let passphrase = read_passphrase(NEW_PASSPHRASE_ENV, &prompt, true)?;
+    let encrypted = encrypt_key(&key, &passphrase, KdfParams::default())?;
+    // write a new file next to it first, so a failure never loses the key
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+    let suffix = random_bytes(8)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), suffix));
+    write_secret_file(&tmp, &encrypted)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAST: KdfParams = KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_encrypt_decrypt_key() -> Result<()> {
+        let encrypted = encrypt_key(b"secret key", "correct horse", FAST)?;
+        assert!(is_encrypted_key(&encrypted));
+        assert_eq!(&*decrypt_key(&encrypted, "correct horse")?, b"secret key");
+        assert!(decrypt_key(&encrypted, "wrong horse").is_err());
+
+        // the params are authenticated with the key
+        let text = String::from_utf8(encrypted)?;
+        let body = text.lines().skip(1).take_while(|l| !l.starts_with("-----"));
+        let mut data = STANDARD.decode(body.collect::<String>())?;
+        data[4] ^= 1;
+        let tampered = format!(
+            "{}\n{}\n{}",
+            ENCRYPTED_KEY_BEGIN,
+            STANDARD.encode(&data),
+            ENCRYPTED_KEY_END
+        );
+        assert!(decrypt_key(tampered.as_bytes(), "correct horse").is_err());
+
+        for params in [
+            KdfParams {
+                m_cost: MAX_M_COST + 1,
+                ..FAST
+            },
+            KdfParams {
+                t_cost: MAX_T_COST + 1,
+                ..FAST
+            },
+            KdfParams {
+                p_cost: MAX_P_COST + 1,
+                ..FAST
+            },
+        ] {
+            assert!(derive_key("correct horse", &[0; SALT_LEN], params).is_err());
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,42 @@
 mod b64;
+mod b64_pack;
+mod codec;
 mod csv_convert;
 mod gen_pass;
+mod gen_pattern;
+mod gen_token;
 mod http_serve;
+mod jwt;
+mod key_wrap;
+mod otp;
+mod pass_audit;
+mod pass_policy;
 mod text;
+mod text_crypt;
+mod text_key;
 
-pub use csv_convert::process_csv;
-pub use gen_pass::process_gen_pass;
+pub use csv_convert::{process_csv, process_csv_render, RenderedRow};
+pub use gen_pass::{
+    gen_pass_entropy, load_wordlist, process_gen_pass, process_gen_pass_bulk,
+    process_gen_passphrase, render_pass_records, GenPassConfig, PassRecord,
+};
 
-pub use b64::{process_decode, process_encode};
+pub use b64::{
+    process_datauri_decode, process_datauri_encode, process_decode, process_decode_lenient,
+    process_encode, DataUri,
+};
+pub use b64_pack::{process_pack, process_unpack, Bundle, BundleEntry};
+pub use codec::{process_codec_decode, process_codec_encode};
+pub use gen_pattern::process_gen_pattern;
+pub use gen_token::{process_gen_token, process_gen_ulid, process_gen_uuid};
 pub use http_serve::process_http_serve;
+pub use jwt::{process_jwt_decode, process_jwt_sign, process_jwt_verify, JwtValidation};
+pub use key_wrap::process_key_rekey;
+pub use otp::{
+    decode_otp_secret, otpauth_uri, process_hotp, process_hotp_verify, process_otp_secret,
+    process_totp, process_totp_verify, unix_now, OtpConfig,
+};
+pub use pass_audit::{process_pass_audit, AuditReport, CrackTimes};
+pub use pass_policy::{load_policy, process_gen_pass_policy, PasswordPolicy, PolicyClass};
 pub use text::{process_generate, process_text_sign, process_text_verify};
+pub use text_crypt::{process_text_decrypt, process_text_encrypt};
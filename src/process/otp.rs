@@ -0,0 +1,227 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Ok, Result};
+use data_encoding::{BASE32_NOPAD, BASE32_NOPAD_NOCASE};
+use hmac::{digest::KeyInit, Hmac, Mac};
+use percent_encoding::utf8_percent_encode;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+use super::{codec::URL_ENCODE_SET, gen_pass::random_bytes};
+use crate::OtpAlgorithm;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OtpConfig {
+    pub algorithm: OtpAlgorithm,
+    /// 6 to 8
+    pub digits: u8,
+    /// TOTP time step in seconds
+    pub period: u64,
+}
+
+impl Default for OtpConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: OtpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+        }
+    }
+}
+
+/// A random secret of `bytes` bytes, base32 encoded without padding
+pub fn process_otp_secret(bytes: usize) -> String {
+    BASE32_NOPAD.encode(&random_bytes(bytes))
+}
+
+/// The `otpauth://totp/` URI authenticator apps read from QR codes
+pub fn otpauth_uri(
+    secret: &str,
+    account: &str,
+    issuer: Option<&str>,
+    config: &OtpConfig,
+) -> String {
+    let encode = |s| utf8_percent_encode(s, URL_ENCODE_SET).to_string();
+    let label = match issuer {
+        Some(issuer) => format!("{}:{}", encode(issuer), encode(account)),
+        None => encode(account),
+    };
+    let mut uri = format!("otpauth://totp/{}?secret={}", label, secret);
+    if let Some(issuer) = issuer {
+        uri.push_str(&format!("&issuer={}", encode(issuer)));
+    }
+    uri.push_str(&format!(
+        "&algorithm={}&digits={}&period={}",
+        config.algorithm.to_string().to_uppercase(),
+        config.digits,
+        config.period
+    ));
+    uri
+}
+
+/// Decode a base32 secret, case, spaces and padding don't matter
+pub fn decode_otp_secret(secret: &str) -> Result<Vec<u8>> {
+    let secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>();
+    let key = BASE32_NOPAD_NOCASE
+        .decode(secret.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Invalid base32 secret: {}", e))?;
+    if key.is_empty() {
+        anyhow::bail!("The secret is empty");
+    }
+    Ok(key)
+}
+
+/// RFC 4226 code for `counter`
+pub fn process_hotp(key: &[u8], counter: u64, config: &OtpConfig) -> Result<String> {
+    if !(6..=8).contains(&config.digits) {
+        anyhow::bail!("An OTP has 6 to 8 digits, got {}", config.digits);
+    }
+    let msg = counter.to_be_bytes();
+    let hash = match config.algorithm {
+        OtpAlgorithm::Sha1 => hmac::<Hmac<Sha1>>(key, &msg)?,
+        OtpAlgorithm::Sha256 => hmac::<Hmac<Sha256>>(key, &msg)?,
+        OtpAlgorithm::Sha512 => hmac::<Hmac<Sha512>>(key, &msg)?,
+    };
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let bytes = [
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ];
+    let value = u32::from_be_bytes(bytes) & 0x7fff_ffff;
+    let code = value % 10u32.pow(config.digits as u32);
+    Ok(format!("{:0width$}", code, width = config.digits as usize))
+}
+
+/// RFC 6238 code for the unix `time`
+pub fn process_totp(key: &[u8], time: u64, config: &OtpConfig) -> Result<String> {
+    process_hotp(key, time_step(time, config)?, config)
+}
+
+/// Check a TOTP code within ±`window` steps of `time`, returns the matching
+/// step offset
+pub fn process_totp_verify(
+    key: &[u8],
+    code: &str,
+    time: u64,
+    window: u64,
+    config: &OtpConfig,
+) -> Result<Option<i64>> {
+    let step = time_step(time, config)?;
+    let first = step.saturating_sub(window);
+    let found = find_counter(key, code, first..=step.saturating_add(window), config)?;
+    Ok(found.map(|counter| counter as i64 - step as i64))
+}
+
+/// Check a HOTP code from `counter` up to `window` counters ahead, returns the
+/// matching counter so the next one can be stored
+pub fn process_hotp_verify(
+    key: &[u8],
+    code: &str,
+    counter: u64,
+    window: u64,
+    config: &OtpConfig,
+) -> Result<Option<u64>> {
+    find_counter(key, code, counter..=counter.saturating_add(window), config)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn find_counter(
+    key: &[u8],
+    code: &str,
+    counters: impl Iterator<Item = u64>,
+    config: &OtpConfig,
+) -> Result<Option<u64>> {
+    let mut found = None;
+    // check every counter so the time taken doesn't tell which one matched
+    for counter in counters {
+        let expected = process_hotp(key, counter, config)?;
+        if constant_time_eq(expected.as_bytes(), code.as_bytes()) && found.is_none() {
+            found = Some(counter);
+        }
+    }
+    Ok(found)
+}
+
+fn time_step(time: u64, config: &OtpConfig) -> Result<u64> {
+    if config.period == 0 {
+        anyhow::bail!("The TOTP period must be greater than 0");
+    }
+    Ok(time / config.period)
+}
+
+fn hmac<M: Mac + KeyInit>(key: &[u8], msg: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = <M as Mac>::new_from_slice(key)?;
+    mac.update(msg);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totp_rfc6238_vectors() -> Result<()> {
+        // RFC 6238 appendix B, the key length follows the hash
+        let keys = [
+            (OtpAlgorithm::Sha1, &b"12345678901234567890"[..]),
+            (OtpAlgorithm::Sha256, b"12345678901234567890123456789012"),
+            (
+                OtpAlgorithm::Sha512,
+                b"1234567890123456789012345678901234567890123456789012345678901234",
+            ),
+        ];
+        let vectors = [
+            (59, ["94287082", "46119246", "90693936"]),
+            (1111111109, ["07081804", "68084774", "25091201"]),
+            (1234567890, ["89005924", "91819424", "93441116"]),
+            (20000000000, ["65353130", "77737706", "47863826"]),
+        ];
+        for (time, codes) in vectors {
+            for ((algorithm, key), code) in keys.iter().zip(codes) {
+                let config = OtpConfig {
+                    algorithm: *algorithm,
+                    digits: 8,
+                    period: 30,
+                };
+                assert_eq!(process_totp(key, time, &config)?, code);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_otp_verify() -> Result<()> {
+        let config = OtpConfig::default();
+        let key = decode_otp_secret(&process_otp_secret(20))?;
+        let code = process_totp(&key, 1000, &config)?;
+        assert_eq!(process_totp_verify(&key, &code, 1000, 1, &config)?, Some(0));
+        assert_eq!(
+            process_totp_verify(&key, &code, 1030, 1, &config)?,
+            Some(-1)
+        );
+        assert_eq!(process_totp_verify(&key, &code, 1090, 1, &config)?, None);
+
+        // RFC 4226 appendix D
+        let key = b"12345678901234567890";
+        assert_eq!(process_hotp(key, 9, &config)?, "520489");
+        assert_eq!(process_hotp_verify(key, "520489", 5, 5, &config)?, Some(9));
+        assert_eq!(process_hotp_verify(key, "520489", 5, 3, &config)?, None);
+        Ok(())
+    }
+}
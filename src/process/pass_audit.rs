@@ -0,0 +1,124 @@
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read},
+};
+
+use anyhow::{Ok, Result};
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+use super::csv_convert::serialize_output;
+use crate::OutputFormat;
+
+/// zxcvbn estimate of one password, it never holds the password itself
+#[derive(Debug, Serialize)]
+pub struct AuditReport {
+    /// 1-based line of the password in the input
+    pub line: usize,
+    /// 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    pub guesses: u64,
+    pub guesses_log10: f64,
+    pub crack_times: CrackTimes,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrackTimes {
+    /// 100 guesses per hour
+    pub online_throttled: String,
+    /// 10 guesses per second
+    pub online_unthrottled: String,
+    /// 10k guesses per second
+    pub offline_slow_hash: String,
+    /// 10B guesses per second
+    pub offline_fast_hash: String,
+}
+
+/// Estimate every non-blank line of the input, `user_inputs` are words the
+/// passwords shouldn't be built from, such as usernames. Without a format the
+/// reports are rendered as text.
+pub fn process_pass_audit(
+    reader: &mut dyn Read,
+    user_inputs: &[&str],
+    format: Option<OutputFormat>,
+) -> Result<String> {
+    let mut reports = Vec::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let password = line.trim_end_matches('\r');
+        if password.is_empty() {
+            continue;
+        }
+        let estimate = zxcvbn(password, user_inputs)?;
+        let times = estimate.crack_times();
+        let feedback = estimate.feedback().as_ref();
+        reports.push(AuditReport {
+            line: i + 1,
+            score: estimate.score(),
+            guesses: estimate.guesses(),
+            guesses_log10: estimate.guesses_log10(),
+            crack_times: CrackTimes {
+                online_throttled: times.online_throttling_100_per_hour().to_string(),
+                online_unthrottled: times.online_no_throttling_10_per_second().to_string(),
+                offline_slow_hash: times.offline_slow_hashing_1e4_per_second().to_string(),
+                offline_fast_hash: times.offline_fast_hashing_1e10_per_second().to_string(),
+            },
+            warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+            suggestions: feedback
+                .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+        });
+    }
+
+    match format {
+        // json ends without a newline, yaml ends with one
+        Some(OutputFormat::Json) => Ok(serialize_output(&reports, OutputFormat::Json)? + "\n"),
+        Some(format) => serialize_output(&reports, format),
+        None => Ok(reports.iter().map(|r| r.to_string()).collect()),
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "line {}: score {}/4, 10^{:.1} guesses",
+            self.line, self.score, self.guesses_log10
+        )?;
+        let times = &self.crack_times;
+        writeln!(f, "  online, throttled:   {}", times.online_throttled)?;
+        writeln!(f, "  online, unthrottled: {}", times.online_unthrottled)?;
+        writeln!(f, "  offline, slow hash:  {}", times.offline_slow_hash)?;
+        writeln!(f, "  offline, fast hash:  {}", times.offline_fast_hash)?;
+        if let Some(warning) = &self.warning {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+        for suggestion in &self.suggestions {
+            writeln!(f, "  suggestion: {}", suggestion)?;
+        }
+        fmt::Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_audit() -> Result<()> {
+        let input = "password\n\nrcli-admin-2024\nj8#Kd92!xQ0v$Lm3\n";
+        let ret = process_pass_audit(&mut input.as_bytes(), &["rcli"], Some(OutputFormat::Json))?;
+        let reports: serde_json::Value = serde_json::from_str(&ret)?;
+        assert_eq!(reports.as_array().unwrap().len(), 3);
+        assert_eq!(reports[0]["score"], 0);
+        assert_eq!(reports[1]["line"], 3);
+        assert_eq!(reports[2]["score"], 4);
+        assert!(!ret.contains("rcli-admin") && !ret.contains("j8#K"));
+        assert!(ret.ends_with("]\n"));
+        let text = process_pass_audit(&mut input.as_bytes(), &[], None)?;
+        assert!(!text.contains("rcli-admin") && !text.contains("j8#K"));
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::{Ok, Result};
+use serde::Deserialize;
+
+use super::gen_pass::{char_classes, parse_charset, MAX_ATTEMPTS};
+use crate::{process_gen_pass, GenPassConfig};
+
+/// Length used when neither the user nor the policy pins one
+const DEFAULT_LENGTH: u8 = 16;
+
+/// Password rules of one system, loaded from a named profile of a policy file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: Option<u8>,
+    pub max_length: Option<u8>,
+    /// classes that must appear at least once
+    pub require: Vec<PolicyClass>,
+    /// chars that may not appear at all
+    pub forbidden: String,
+    /// the longest run of one char, e.g. 2 allows "aa" but not "aaa"
+    pub max_repeats: Option<u8>,
+    /// allowed first chars as a charset, e.g. "a-zA-Z"
+    pub first_char: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyClass {
+    Uppercase,
+    Lowercase,
+    Number,
+    Symbol,
+}
+
+impl PolicyClass {
+    fn matches(self, c: char) -> bool {
+        match self {
+            PolicyClass::Uppercase => c.is_uppercase(),
+            PolicyClass::Lowercase => c.is_lowercase(),
+            PolicyClass::Number => c.is_ascii_digit(),
+            PolicyClass::Symbol => !c.is_alphanumeric(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PolicyClass::Uppercase => "uppercase",
+            PolicyClass::Lowercase => "lowercase",
+            PolicyClass::Number => "number",
+            PolicyClass::Symbol => "symbol",
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// The default length, moved into the allowed range
+    pub fn default_length(&self) -> u8 {
+        let min = self.min_length.unwrap_or(1);
+        let max = self.max_length.unwrap_or(u8::MAX);
+        DEFAULT_LENGTH.clamp(min, max)
+    }
+
+    /// Every rule the password breaks, empty if it satisfies the policy.
+    /// The messages never contain the password.
+    pub fn check(&self, password: &str) -> Result<Vec<String>> {
+        let mut ret = Vec::new();
+        let length = password.chars().count();
+        if let Some(min) = self.min_length {
+            if length < min as usize {
+                ret.push(format!("is {} chars long, the minimum is {}", length, min));
+            }
+        }
+        if let Some(max) = self.max_length {
+            if length > max as usize {
+                ret.push(format!("is {} chars long, the maximum is {}", length, max));
+            }
+        }
+        for class in &self.require {
+            if !password.chars().any(|c| class.matches(c)) {
+                ret.push(format!("has no {} chars", class.name()));
+            }
+        }
+        let mut forbidden = self
+            .forbidden
+            .chars()
+            .filter(|&c| password.contains(c))
+            .map(|c| format!("{:?}", c))
+            .collect::<Vec<_>>();
+        forbidden.sort();
+        forbidden.dedup();
+        if !forbidden.is_empty() {
+            ret.push(format!("contains forbidden chars {}", forbidden.join(", ")));
+        }
+        if let Some(max) = self.max_repeats {
+            let longest = longest_run(password);
+            if longest > max as usize {
+                ret.push(format!(
+                    "repeats a char {} times in a row, the maximum is {}",
+                    longest, max
+                ));
+            }
+        }
+        if let Some(spec) = &self.first_char {
+            let allowed = parse_charset(spec, true)?;
+            match password.chars().next() {
+                Some(c) if c.is_ascii() && allowed.contains(&(c as u8)) => {}
+                _ => ret.push(format!("does not start with one of {:?}", spec)),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The parts of the policy that can steer the generator, the rest is checked
+    pub fn apply(&self, config: &GenPassConfig) -> GenPassConfig {
+        let mut config = config.clone();
+        config.exclude.push_str(&self.forbidden);
+        config.no_repeat |= self.max_repeats == Some(1);
+        for class in &self.require {
+            match class {
+                PolicyClass::Uppercase => config.min_upper = config.min_upper.max(1),
+                // every enabled class gets at least one char, and lowercase has no minimum
+                PolicyClass::Lowercase => {}
+                PolicyClass::Number => config.min_digits = config.min_digits.max(1),
+                PolicyClass::Symbol => config.min_symbols = config.min_symbols.max(1),
+            }
+        }
+        config
+    }
+
+    /// Fail early when `config`, already applied, can never satisfy the policy
+    fn check_feasible(&self, config: &GenPassConfig) -> Result<()> {
+        for class in &self.require {
+            let enabled = match class {
+                PolicyClass::Uppercase => config.upper,
+                PolicyClass::Lowercase => config.lower,
+                PolicyClass::Number => config.number,
+                PolicyClass::Symbol => config.symbol,
+            };
+            if !enabled {
+                anyhow::bail!(
+                    "The policy requires {} chars, but they are disabled",
+                    class.name()
+                );
+            }
+        }
+        let classes = char_classes(config)?;
+        for class in &self.require {
+            if !classes.iter().any(|c| c.name == class.name()) {
+                anyhow::bail!(
+                    "The policy requires {} chars, but the charset has none",
+                    class.name()
+                );
+            }
+        }
+        let required = classes.iter().map(|c| c.min).sum::<usize>();
+        if required > config.length as usize {
+            anyhow::bail!(
+                "Password length {} is too short for the {} chars the policy requires",
+                config.length,
+                required
+            );
+        }
+        if let Some(spec) = &self.first_char {
+            let allowed = parse_charset(spec, true)?;
+            if !classes
+                .iter()
+                .any(|c| c.chars.iter().any(|c| allowed.contains(c)))
+            {
+                anyhow::bail!(
+                    "No allowed char is left to start with, first_char is {:?}",
+                    spec
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let (Some(min), Some(max)) = (self.min_length, self.max_length) {
+            if min > max {
+                anyhow::bail!("min_length {} is greater than max_length {}", min, max);
+            }
+        }
+        if self.max_repeats == Some(0) {
+            anyhow::bail!("max_repeats must be at least 1");
+        }
+        if let Some(spec) = &self.first_char {
+            if parse_charset(spec, true)?.is_empty() {
+                anyhow::bail!("first_char allows no char");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Load a profile from a YAML (or JSON) file, or a TOML file by its extension.
+/// The profile may be left out when the file has only one.
+pub fn load_policy(path: &str, profile: Option<&str>) -> Result<PasswordPolicy> {
+    let content = fs::read_to_string(path)?;
+    let mut policies: BTreeMap<String, PasswordPolicy> = if path.ends_with(".toml") {
+        toml::from_str(&content)?
+    } else {
+        serde_yaml::from_str(&content)?
+    };
+    let names = policies.keys().cloned().collect::<Vec<_>>().join(", ");
+    let name = match profile {
+        Some(name) => name.to_string(),
+        None if policies.len() == 1 => names.clone(),
+        None => anyhow::bail!("{} has several profiles, pick one of: {}", path, names),
+    };
+    let Some(policy) = policies.remove(&name) else {
+        anyhow::bail!("No profile {} in {}, available: {}", name, path, names);
+    };
+    policy
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid profile {}: {}", name, e))?;
+    Ok(policy)
+}
+
+/// Generate a password from `config` that also satisfies the policy. Required
+/// classes, forbidden chars and `max_repeats: 1` steer the generator, the first
+/// char and longer runs are retried until they fit.
+pub fn process_gen_pass_policy(config: &GenPassConfig, policy: &PasswordPolicy) -> Result<String> {
+    let length = config.length as usize;
+    if policy.min_length.is_some_and(|min| length < min as usize)
+        || policy.max_length.is_some_and(|max| length > max as usize)
+    {
+        anyhow::bail!(
+            "Password length {} is outside of the policy range {}..={}",
+            length,
+            policy.min_length.unwrap_or(1),
+            policy.max_length.unwrap_or(u8::MAX)
+        );
+    }
+    let config = policy.apply(config);
+    policy.check_feasible(&config)?;
+
+    let mut violations = Vec::new();
+    for _ in 0..MAX_ATTEMPTS {
+        let password = process_gen_pass(&config)?;
+        violations = policy.check(&password)?;
+        if violations.is_empty() {
+            return Ok(password);
+        }
+    }
+    anyhow::bail!(
+        "Can't generate a password that satisfies the policy, it {}",
+        violations.join(", ")
+    )
+}
+
+fn longest_run(password: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut prev = None;
+    for c in password.chars() {
+        run = if prev == Some(c) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        prev = Some(c);
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn test_password_policy() -> Result<()> {
+        let dir = TestDir::new("policy");
+        let path = dir.join("policies.yaml");
+        fs::write(
+            &path,
+            r#"
+aws-iam:
+  min_length: 20
+  max_length: 128
+  require: [uppercase, lowercase, number, symbol]
+  forbidden: "\"'"
+  max_repeats: 2
+  first_char: a-zA-Z
+legacy:
+  max_length: 8
+"#,
+        )?;
+        let path = path.to_str().unwrap();
+        assert!(load_policy(path, None).is_err());
+        assert!(load_policy(path, Some("nope")).is_err());
+        let policy = load_policy(path, Some("aws-iam"))?;
+        assert_eq!(policy.default_length(), 20);
+
+        let violations = policy.check("1aaa\"")?;
+        assert_eq!(violations.len(), 5);
+        assert!(violations.iter().all(|v| !v.contains("1aaa")));
+
+        let config = GenPassConfig {
+            length: policy.default_length(),
+            ..Default::default()
+        };
+        let password = process_gen_pass_policy(&config, &policy)?;
+        assert!(policy.check(&password)?.is_empty());
+
+        // infeasible settings fail up front
+        let no_symbol = GenPassConfig {
+            symbol: false,
+            ..config.clone()
+        };
+        assert!(process_gen_pass_policy(&no_symbol, &policy).is_err());
+        let letters = GenPassConfig {
+            charset: Some("a-zA-Z".into()),
+            ..config.clone()
+        };
+        assert!(process_gen_pass_policy(&letters, &policy).is_err());
+        let digits = GenPassConfig {
+            charset: Some("0-9".into()),
+            ..config
+        };
+        let policy = PasswordPolicy {
+            first_char: Some("a-z".into()),
+            ..Default::default()
+        };
+        assert!(process_gen_pass_policy(&digits, &policy).is_err());
+        Ok(())
+    }
+}
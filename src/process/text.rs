@@ -1,10 +1,26 @@
 use anyhow::{Ok, Result};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
-use std::{fs, io::Read, path::Path};
+use sha2::{Digest, Sha512};
+use std::{
+    io::{self, Read},
+    path::Path,
+};
 
-use crate::{get_reader, process_gen_pass, TextSignFormat};
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use super::{
+    gen_pass::random_bytes,
+    key_wrap::{encrypt_key, read_key_file, read_passphrase, KdfParams, PASSPHRASE_ENV},
+    text_key::{decode_ed25519_signing_key, decode_ed25519_verifying_key, encode_ed25519_key},
+};
+use crate::{get_reader, TextKeyFormat, TextSignFormat};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+
+/// Blake3 keys are stored as base64 between these lines
+const BLAKE3_KEY_BEGIN: &str = "-----BEGIN RCLI BLAKE3 KEY-----";
+const BLAKE3_KEY_END: &str = "-----END RCLI BLAKE3 KEY-----";
 
 pub trait TextSign {
     /// Sign the data from the reader and return the signature
@@ -38,26 +54,38 @@ pub struct Ed2519Verify {
     key: VerifyingKey,
 }
 
+/// Ed25519ph (RFC 8032): signs the SHA-512 of the input so it can stream, its
+/// signatures are not valid pure Ed25519 signatures and vice versa
+pub struct Ed25519phSigner {
+    key: SigningKey,
+}
+
+pub struct Ed25519phVerify {
+    key: VerifyingKey,
+}
+
 impl TextSign for Blake3 {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
-        // TODO: improve a perf by reading in chunks
-        let mut buf: Vec<u8> = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        Ok(blake3::keyed_hash(&self.key, &buf).as_bytes().to_vec())
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        io::copy(reader, &mut hasher)?;
+        Ok(hasher.finalize().as_bytes().to_vec())
     }
 }
 
 impl TextVerify for Blake3 {
     fn verify(&self, mut reader: impl Read, sign: &[u8]) -> Result<bool> {
-        let mut buf: Vec<u8> = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        let binding = blake3::keyed_hash(&self.key, &buf);
-        let hash = binding.as_bytes();
-        Ok(hash == sign)
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        io::copy(&mut reader, &mut hasher)?;
+        let Result::Ok(sign) = <[u8; 32]>::try_from(sign) else {
+            return Ok(false);
+        };
+        // blake3::Hash compares in constant time
+        Ok(hasher.finalize() == blake3::Hash::from(sign))
     }
 }
 
 impl TextSign for Ed2519Signer {
+    /// Pure Ed25519 needs the whole message in memory, Ed25519ph doesn't
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
         let mut buf = Vec::<u8>::new();
         reader.read_to_end(&mut buf)?;
@@ -76,6 +104,24 @@ impl TextVerify for Ed2519Verify {
     }
 }
 
+impl TextSign for Ed25519phSigner {
+    fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>> {
+        let mut hasher = Sha512::new();
+        io::copy(reader, &mut hasher)?;
+        let sig = self.key.sign_prehashed(hasher, None)?;
+        Ok(sig.to_bytes().to_vec())
+    }
+}
+
+impl TextVerify for Ed25519phVerify {
+    fn verify(&self, mut reader: impl Read, sign: &[u8]) -> Result<bool> {
+        let mut hasher = Sha512::new();
+        io::copy(&mut reader, &mut hasher)?;
+        let sig = Signature::from_bytes(sign.try_into()?);
+        Ok(self.key.verify_prehashed(hasher, None, &sig).is_ok())
+    }
+}
+
 pub fn process_text_sign(input: &str, key: &str, format: TextSignFormat) -> Result<String> {
     let mut reader = get_reader(input)?;
     let signed = match format {
@@ -83,10 +129,14 @@ pub fn process_text_sign(input: &str, key: &str, format: TextSignFormat) -> Resu
             let signer = Blake3::load(key)?;
             signer.sign(&mut reader)?
         }
-        TextSignFormat::Ed2519 => {
+        TextSignFormat::Ed25519 => {
             let signer = Ed2519Signer::load(key)?;
             signer.sign(&mut reader)?
         }
+        TextSignFormat::Ed25519ph => {
+            let signer = Ed25519phSigner::load(key)?;
+            signer.sign(&mut reader)?
+        }
     };
     let signed = URL_SAFE_NO_PAD.encode(signed);
     Ok(signed)
@@ -105,18 +155,43 @@ pub fn process_text_verify(
             let verifier = Blake3::load(key)?;
             verifier.verify(&mut reader, &sig)?
         }
-        TextSignFormat::Ed2519 => {
+        TextSignFormat::Ed25519 => {
             let verifier = Ed2519Verify::load(key)?;
             verifier.verify(&mut reader, &sig)?
         }
+        TextSignFormat::Ed25519ph => {
+            let verifier = Ed25519phVerify::load(key)?;
+            verifier.verify(&mut reader, &sig)?
+        }
     };
     Ok(verified)
 }
 
-pub fn process_generate(format: TextSignFormat) -> Result<Vec<Vec<u8>>> {
+/// The key files, the private or shared key first. With `encrypt` that one is
+/// wrapped under a passphrase.
+pub fn process_generate(
+    format: TextSignFormat,
+    key_format: TextKeyFormat,
+    encrypt: bool,
+) -> Result<Vec<Vec<u8>>> {
+    let mut keys = generate_keys(format, key_format)?;
+    if encrypt {
+        let passphrase = read_passphrase(PASSPHRASE_ENV, "New passphrase: ", true)?;
+        keys[0] = encrypt_key(&keys[0], &passphrase, KdfParams::default())?;
+    }
+    Ok(keys)
+}
+
+fn generate_keys(format: TextSignFormat, key_format: TextKeyFormat) -> Result<Vec<Vec<u8>>> {
     match format {
+        TextSignFormat::Blake3 if key_format != TextKeyFormat::Raw => {
+            anyhow::bail!("Blake3 keys have a single format, --key-format is for ed25519 keys")
+        }
         TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed2519 => Ed2519Signer::generate(),
+        // both use the same Ed25519 key pairs
+        TextSignFormat::Ed25519 | TextSignFormat::Ed25519ph => {
+            encode_ed25519_key(&SigningKey::generate(&mut OsRng), key_format)
+        }
     }
 }
 
@@ -125,6 +200,11 @@ impl Blake3 {
         Self { key }
     }
 
+    /// The 32-byte key, `text encrypt` uses the same key files
+    pub(super) fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
     // equal to pub fn try_new<T: AsRef<[u8]>>(key: T) -> Result<Self>
     // From<T> for U: T->U
     // AsRef<T> for U: U(ref) -> &T
@@ -132,34 +212,53 @@ impl Blake3 {
     // "a.txt".to_string() (String) => &[u8]
     pub fn try_new(key: impl AsRef<[u8]>) -> Result<Self> {
         let key = key.as_ref();
-        let key = (&key[..32]).try_into()?;
+        let key = <[u8; 32]>::try_from(key).map_err(|_| {
+            anyhow::anyhow!("Invalid blake3 key: expected 32 bytes, got {}", key.len())
+        })?;
         let signer = Blake3::new(key);
         Ok(signer)
     }
+
+    /// The key file content, the key in base64 between a header and a footer
+    pub fn encode(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n",
+            BLAKE3_KEY_BEGIN,
+            STANDARD.encode(self.key),
+            BLAKE3_KEY_END
+        )
+    }
+
+    /// Parse a key file. A file without the header is taken as the raw key
+    /// earlier versions wrote.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let Result::Ok(text) = std::str::from_utf8(data) else {
+            return Self::try_new(data);
+        };
+        let Some(body) = text.trim().strip_prefix(BLAKE3_KEY_BEGIN) else {
+            return Self::try_new(data);
+        };
+        let body = body
+            .strip_suffix(BLAKE3_KEY_END)
+            .ok_or_else(|| anyhow::anyhow!("Invalid blake3 key: missing {}", BLAKE3_KEY_END))?;
+        let body = body.split_whitespace().collect::<String>();
+        let key = STANDARD
+            .decode(body)
+            .map_err(|e| anyhow::anyhow!("Invalid blake3 key: {}", e))?;
+        Self::try_new(key)
+    }
 }
 
 impl Ed2519Signer {
     pub fn new(key: SigningKey) -> Self {
         Self { key }
     }
-
-    pub fn try_new(key: &[u8]) -> Result<Self> {
-        let key = key.try_into()?;
-        let signer = Ed2519Signer::new(key);
-        Ok(signer)
-    }
 }
 
 impl Ed2519Verify {
     pub fn new(key: VerifyingKey) -> Self {
         Self { key }
     }
-
-    pub fn try_new(key: &[u8]) -> Result<Self> {
-        let key = VerifyingKey::from_bytes(key.try_into()?)?;
-        let verifier = Ed2519Verify::new(key);
-        Ok(verifier)
-    }
 }
 
 impl KeyLoader for Blake3 {
@@ -167,35 +266,58 @@ impl KeyLoader for Blake3 {
     where
         Self: Sized,
     {
-        let key = fs::read(path)?;
-        Self::try_new(key)
+        let key = read_key_file(path)?;
+        Self::decode(&key)
     }
 }
 
 impl KeyLoader for Ed2519Signer {
+    /// Raw, PEM (PKCS#8), OpenSSH and JWK keys are detected
     fn load(path: impl AsRef<Path>) -> Result<Self>
     where
         Self: Sized,
     {
-        let key = fs::read(path)?;
-        Self::try_new(&key)
+        let key = read_key_file(path)?;
+        Ok(Self::new(decode_ed25519_signing_key(&key)?))
     }
 }
 
 impl KeyLoader for Ed2519Verify {
+    /// Like `Ed2519Signer`, except that 32 raw bytes must be the public key.
+    /// PEM, OpenSSH and JWK private keys are accepted too.
+    fn load(path: impl AsRef<Path>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let key = read_key_file(path)?;
+        Ok(Self::new(decode_ed25519_verifying_key(&key)?))
+    }
+}
+
+impl KeyLoader for Ed25519phSigner {
     fn load(path: impl AsRef<Path>) -> Result<Self>
     where
         Self: Sized,
     {
-        let key = fs::read(path)?;
-        Self::try_new(&key)
+        let key = Ed2519Signer::load(path)?.key;
+        Ok(Self { key })
+    }
+}
+
+impl KeyLoader for Ed25519phVerify {
+    fn load(path: impl AsRef<Path>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let key = Ed2519Verify::load(path)?.key;
+        Ok(Self { key })
     }
 }
 
 impl KeyGenerator for Blake3 {
     fn generate() -> Result<Vec<Vec<u8>>> {
-        let key = process_gen_pass(32, true, true, true, true)?;
-        Ok(vec![key.as_bytes().to_vec()])
+        let key = random_bytes(32).try_into().expect("32 bytes");
+        Ok(vec![Blake3::new(key).encode().into_bytes()])
     }
 }
 
@@ -203,8 +325,63 @@ impl KeyGenerator for Ed2519Signer {
     fn generate() -> Result<Vec<Vec<u8>>> {
         let mut csprng = OsRng;
         let sk = SigningKey::generate(&mut csprng);
-        let pk = sk.verifying_key().to_bytes().to_vec();
-        let sk = sk.to_bytes().to_vec();
-        Ok(vec![sk, pk])
+        encode_ed25519_key(&sk, TextKeyFormat::Raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_sign_verify() -> Result<()> {
+        // larger than any single read, so the hashers see many chunks
+        let input = || io::repeat(b'a').take(3 << 20);
+        let mut data = Vec::new();
+        input().read_to_end(&mut data)?;
+
+        let blake3 = Blake3::new([7; 32]);
+        let sig = blake3.sign(&mut input())?;
+        assert_eq!(sig, blake3::keyed_hash(&[7; 32], &data).as_bytes());
+        assert!(blake3.verify(input(), &sig)?);
+        assert!(!blake3.verify(input(), &sig[..31])?);
+
+        let sk = SigningKey::generate(&mut OsRng);
+        let pk = sk.verifying_key();
+        let signer = Ed25519phSigner { key: sk.clone() };
+        let sig = signer.sign(&mut input())?;
+        assert!(Ed25519phVerify { key: pk }.verify(input(), &sig)?);
+        // a prehashed signature is no pure Ed25519 signature of the same data
+        assert!(!Ed2519Verify::new(pk).verify(&data[..], &sig)?);
+        let pure = Ed2519Signer::new(sk).sign(&mut &data[..])?;
+        assert!(!Ed25519phVerify { key: pk }.verify(input(), &pure)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blake3_key_file() -> Result<()> {
+        let key = Blake3::generate()?.remove(0);
+        assert!(key.starts_with(BLAKE3_KEY_BEGIN.as_bytes()));
+        let blake3 = Blake3::decode(&key)?;
+        assert_ne!(blake3.key, [0; 32]);
+        assert_eq!(Blake3::decode(blake3.encode().as_bytes())?.key, blake3.key);
+        // raw keys from earlier versions
+        assert_eq!(Blake3::decode(&[b'a'; 32])?.key, [b'a'; 32]);
+
+        let err = Blake3::decode(b"short").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid blake3 key: expected 32 bytes, got 5"
+        );
+        let truncated = format!("{}\n{}\n", BLAKE3_KEY_BEGIN, STANDARD.encode([1; 16]));
+        assert!(Blake3::decode(truncated.as_bytes()).is_err());
+        let short = format!(
+            "{}\n{}\n{}",
+            BLAKE3_KEY_BEGIN,
+            STANDARD.encode([1; 16]),
+            BLAKE3_KEY_END
+        );
+        assert!(Blake3::decode(short.as_bytes()).is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,232 @@
+use std::io::{Read, Write};
+
+use anyhow::{Ok, Result};
+use chacha20poly1305::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        Payload,
+    },
+    KeyInit, XChaCha20Poly1305,
+};
+use zeroize::Zeroizing;
+
+use super::{
+    gen_pass::random_bytes,
+    key_wrap::{derive_key, read_passphrase, KdfParams, PASSPHRASE_ENV},
+    text::{Blake3, KeyLoader},
+};
+
+const MAGIC: &[u8] = b"rcli-enc";
+const VERSION: u8 = 1;
+const MODE_KEY: u8 = 1;
+const MODE_PASSPHRASE: u8 = 2;
+const SALT_LEN: usize = 16;
+/// the XChaCha20 nonce less the STREAM counter and last chunk flag
+const NONCE_LEN: usize = 19;
+const TAG_LEN: usize = 16;
+const CHUNK_SIZE: usize = 64 * 1024;
+/// a larger chunk size in a header is refused rather than allocated
+const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Encrypt with the 32-byte shared key in the `key` file, the key files of
+/// `text generate --format blake3` work, or with a passphrase without one.
+///
+/// The output is a header followed by chunks of XChaCha20-Poly1305 in the
+/// STREAM construction, so inputs of any size stream through and a truncated
+/// or reordered output fails to decrypt. The header is
+/// `magic | version | mode | chunk size | [m_cost | t_cost | p_cost | salt] | nonce`,
+/// the numbers are big endian u32s, the Argon2id params and salt are only
+/// there in passphrase mode, and each chunk authenticates the whole header.
+pub fn process_text_encrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    key: Option<&str>,
+) -> Result<()> {
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+    let cipher_key = match key {
+        Some(key) => {
+            header.push(MODE_KEY);
+            header.extend_from_slice(&(CHUNK_SIZE as u32).to_be_bytes());
+            Zeroizing::new(*Blake3::load(key)?.key())
+        }
+        None => {
+            let passphrase = read_passphrase(PASSPHRASE_ENV, "Passphrase: ", true)?;
+            let params = KdfParams::default();
+            let salt = random_bytes(SALT_LEN);
+            header.push(MODE_PASSPHRASE);
+            header.extend_from_slice(&(CHUNK_SIZE as u32).to_be_bytes());
+            for v in [params.m_cost, params.t_cost, params.p_cost] {
+                header.extend_from_slice(&v.to_be_bytes());
+            }
+            header.extend_from_slice(&salt);
+            derive_key(&passphrase, &salt, params)?
+        }
+    };
+    let nonce = random_bytes(NONCE_LEN);
+    header.extend_from_slice(&nonce);
+    writer.write_all(&header)?;
+
+    let cipher = XChaCha20Poly1305::new(cipher_key.as_ref().into());
+    let mut stream = EncryptorBE32::from_aead(cipher, nonce.as_slice().into());
+    let encrypt_error = |_| anyhow::anyhow!("Failed to encrypt the input");
+    let mut chunk = read_chunk(reader, CHUNK_SIZE)?;
+    loop {
+        let next = match chunk.len() {
+            CHUNK_SIZE => read_chunk(reader, CHUNK_SIZE)?,
+            _ => Vec::new(),
+        };
+        let payload = Payload {
+            msg: &chunk,
+            aad: &header,
+        };
+        if next.is_empty() {
+            let ciphertext = stream.encrypt_last(payload).map_err(encrypt_error)?;
+            writer.write_all(&ciphertext)?;
+            return Ok(());
+        }
+        let ciphertext = stream.encrypt_next(payload).map_err(encrypt_error)?;
+        writer.write_all(&ciphertext)?;
+        chunk = next;
+    }
+}
+
+/// Decrypt `process_text_encrypt` output, `key` is needed unless it was
+/// encrypted with a passphrase.
+///
+/// Chunks are written as soon as they are authenticated, so on an error the
+/// output holds a prefix of the plaintext and should be discarded.
+pub fn process_text_decrypt(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    key: Option<&str>,
+) -> Result<()> {
+    let mut header = read_exact(reader, MAGIC.len() + 6)?;
+    if &header[..MAGIC.len()] != MAGIC {
+        anyhow::bail!("The input is not encrypted by rcli");
+    }
+    let version = header[MAGIC.len()];
+    if version != VERSION {
+        anyhow::bail!("Unsupported encryption version {}", version);
+    }
+    let mode = header[MAGIC.len() + 1];
+    let chunk_size = u32::from_be_bytes(header[MAGIC.len() + 2..].try_into()?) as usize;
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        anyhow::bail!("Invalid chunk size {} in the header", chunk_size);
+    }
+    let cipher_key = match (mode, key) {
+        (MODE_KEY, Some(key)) => Zeroizing::new(*Blake3::load(key)?.key()),
+        (MODE_KEY, None) => anyhow::bail!("The input is encrypted with a key, pass it with --key"),
+        (MODE_PASSPHRASE, None) => {
+            let params = read_exact(reader, 12 + SALT_LEN)?;
+            header.extend_from_slice(&params);
+            let num = |i: usize| u32::from_be_bytes(params[i * 4..i * 4 + 4].try_into().unwrap());
+            let kdf = KdfParams {
+                m_cost: num(0),
+                t_cost: num(1),
+                p_cost: num(2),
+            };
+            // the header is untrusted, don't ask for a passphrase it can't use
+            kdf.validate()?;
+            let passphrase = read_passphrase(PASSPHRASE_ENV, "Passphrase: ", false)?;
+            derive_key(&passphrase, &params[12..], kdf)?
+        }
+        (MODE_PASSPHRASE, Some(_)) => {
+            anyhow::bail!("The input is encrypted with a passphrase, not a key")
+        }
+        (mode, _) => anyhow::bail!("Unsupported encryption mode {}", mode),
+    };
+    let nonce = read_exact(reader, NONCE_LEN)?;
+    header.extend_from_slice(&nonce);
+
+    let cipher = XChaCha20Poly1305::new(cipher_key.as_ref().into());
+    let mut stream = DecryptorBE32::from_aead(cipher, nonce.as_slice().into());
+    let decrypt_error = |_| anyhow::anyhow!("Wrong key, or the input is corrupted or truncated");
+    let size = chunk_size + TAG_LEN;
+    let mut chunk = read_chunk(reader, size)?;
+    loop {
+        let next = match chunk.len() {
+            n if n == size => read_chunk(reader, size)?,
+            _ => Vec::new(),
+        };
+        let payload = Payload {
+            msg: &chunk,
+            aad: &header,
+        };
+        if next.is_empty() {
+            let plain = stream.decrypt_last(payload).map_err(decrypt_error)?;
+            writer.write_all(&plain)?;
+            return Ok(());
+        }
+        let plain = stream.decrypt_next(payload).map_err(decrypt_error)?;
+        writer.write_all(&plain)?;
+        chunk = next;
+    }
+}
+
+/// Up to `size` bytes, fewer only at the end of the input
+fn read_chunk(reader: &mut dyn Read, size: usize) -> Result<Vec<u8>> {
+    let mut ret = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut ret)?;
+    Ok(ret)
+}
+
+fn read_exact(reader: &mut dyn Read, size: usize) -> Result<Vec<u8>> {
+    let ret = read_chunk(reader, size)?;
+    if ret.len() < size {
+        anyhow::bail!("The input is not encrypted by rcli, its header is truncated");
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{process_generate, utils::TestDir, TextKeyFormat, TextSignFormat};
+
+    #[test]
+    fn test_encrypt_decrypt() -> Result<()> {
+        let dir = TestDir::new("text_crypt");
+        let key = dir.join("key.txt");
+        let key_file = process_generate(TextSignFormat::Blake3, TextKeyFormat::Raw, false)?;
+        fs::write(&key, &key_file[0])?;
+        let key = key.to_str();
+
+        // empty, a single chunk, and a multiple of the chunk size
+        for len in [0, 1000, CHUNK_SIZE * 2] {
+            let data = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let mut encrypted = Vec::new();
+            process_text_encrypt(&mut &data[..], &mut encrypted, key)?;
+            let chunks = len.div_ceil(CHUNK_SIZE).max(1);
+            assert_eq!(encrypted.len(), 33 + len + chunks * TAG_LEN);
+
+            let mut plain = Vec::new();
+            process_text_decrypt(&mut &encrypted[..], &mut plain, key)?;
+            assert_eq!(plain, data);
+
+            // tampered, or truncated at a chunk boundary
+            let mut tampered = encrypted.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(process_text_decrypt(&mut &tampered[..], &mut Vec::new(), key).is_err());
+            if len > CHUNK_SIZE {
+                let truncated = &encrypted[..33 + CHUNK_SIZE + TAG_LEN];
+                assert!(process_text_decrypt(&mut &truncated[..], &mut Vec::new(), key).is_err());
+            }
+        }
+        assert!(process_text_decrypt(&mut &b"plain text"[..], &mut Vec::new(), key).is_err());
+
+        // a passphrase header asking for 4 TiB is refused up front
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&[VERSION, MODE_PASSPHRASE]);
+        header.extend_from_slice(&(CHUNK_SIZE as u32).to_be_bytes());
+        for v in [u32::MAX, 1, 1] {
+            header.extend_from_slice(&v.to_be_bytes());
+        }
+        header.extend_from_slice(&[0; SALT_LEN + NONCE_LEN]);
+        let ret = process_text_decrypt(&mut &header[..], &mut Vec::new(), None);
+        assert!(ret.unwrap_err().to_string().contains("memory cost"));
+        Ok(())
+    }
+}
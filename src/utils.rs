@@ -1,5 +1,9 @@
 use anyhow::Result;
-use std::{fs::File, io::Read};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -9,3 +13,240 @@ pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
     };
     Ok(reader)
 }
+
+pub fn get_writer(output: &str) -> Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(BufWriter::new(std::io::stdout()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    Ok(writer)
+}
+
+/// Write `output` through `f` so that a failure leaves nothing behind. Stdout only
+/// gets the data once `f` succeeds, see `write_file_atomic` for files.
+pub fn write_output(output: &str, f: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    if output != "-" {
+        return write_file_atomic(output, f);
+    }
+    let mut buf = Vec::new();
+    f(&mut buf)?;
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&buf)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Write a new `path` through `f` into a file next to it, then rename that into
+/// place. A failure leaves the old file alone, and the old file can still be
+/// read while the new one is written.
+pub fn write_file_atomic(
+    path: impl AsRef<Path>,
+    f: impl FnOnce(&mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file", path.display()))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{:016x}.tmp",
+        name.to_string_lossy(),
+        rand::random::<u64>()
+    ));
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .map_err(|e| anyhow::anyhow!("Cannot create {}: {}", tmp.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let ret = f(&mut writer)
+        .and_then(|_| Ok(writer.flush()?))
+        .and_then(|_| Ok(fs::rename(&tmp, path)?));
+    if ret.is_err() {
+        drop(writer);
+        let _ = fs::remove_file(&tmp);
+    }
+    ret
+}
+
+/// Write a secret to a new file only the owner can access, an existing file is
+/// never overwritten
+pub fn write_secret_file(path: impl AsRef<Path>, data: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| anyhow::anyhow!("Cannot create {}: {}", path.display(), e))?;
+    if let Err(e) = file.write_all(data).and_then(|_| file.sync_all()) {
+        drop(file);
+        let _ = fs::remove_file(path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Data is binary if it is not UTF-8 or contains control characters other than whitespace
+pub fn is_binary(data: &[u8]) -> bool {
+    match std::str::from_utf8(data) {
+        Ok(s) => s.chars().any(|c| c.is_control() && !c.is_whitespace()),
+        Err(_) => true,
+    }
+}
+
+/// Format up to `limit` bytes as a classic `hexdump -C` style listing
+pub fn hexdump(data: &[u8], limit: usize) -> String {
+    let mut ret = String::new();
+    for (i, chunk) in data[..data.len().min(limit)].chunks(16).enumerate() {
+        let hex = chunk
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        ret.push_str(&format!("{:08x}  {:<47}  |{}|\n", i * 16, hex, ascii));
+    }
+    if data.len() > limit {
+        ret.push_str(&format!("... {} more bytes\n", data.len() - limit));
+    }
+    ret
+}
+
+/// A reader that drops ascii whitespace from the underlying reader
+pub struct SkipWhitespace<R> {
+    inner: R,
+}
+
+impl<R: Read> SkipWhitespace<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 {
+                return io::Result::Ok(0);
+            }
+            let mut len = 0;
+            for i in 0..n {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[len] = buf[i];
+                    len += 1;
+                }
+            }
+            // a chunk of only whitespace is not EOF, keep reading
+            if len > 0 {
+                return io::Result::Ok(len);
+            }
+        }
+    }
+}
+
+/// A directory of its own for a unit test, removed when dropped
+#[cfg(test)]
+pub(crate) struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    /// `name` only has to be unique within the crate, the pid keeps
+    /// concurrent test runs apart
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rcli-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create the test directory");
+        Self(dir)
+    }
+
+    pub fn join(&self, name: &str) -> std::path::PathBuf {
+        self.0.join(name)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump() {
+        let data = b"Hello, world!\x00\x01\x02ABC";
+        let expected = concat!(
+            "00000000  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 00 01 02  |Hello, world!...|\n",
+            "00000010  41 42 43                                         |ABC|\n",
+        );
+        assert_eq!(hexdump(data, 64), expected);
+
+        let dump = hexdump(&[0xff; 40], 32);
+        assert!(dump.starts_with("00000000  ff ff"));
+        assert!(dump.contains("\n00000010  ff"));
+        assert!(dump.ends_with("|................|\n... 8 more bytes\n"));
+        assert_eq!(hexdump(b"", 16), "");
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary("tabs\tand\r\nnew lines, ünïcode\n".as_bytes()));
+        // a single control char or invalid byte is enough
+        assert!(is_binary(b"text\x00"));
+        assert!(is_binary(b"\x1b[31mred"));
+        assert!(is_binary(b"caf\xe9"));
+    }
+
+    #[test]
+    fn test_write_secret_file() -> Result<()> {
+        let dir = TestDir::new("secret_file");
+        let path = dir.join("secret");
+        write_secret_file(&path, b"secret")?;
+        assert_eq!(fs::read(&path)?, b"secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        }
+        assert!(write_secret_file(&path, b"other").is_err());
+        assert_eq!(fs::read(&path)?, b"secret");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_file_atomic() -> Result<()> {
+        let dir = TestDir::new("write_file_atomic");
+        let path = dir.join("out");
+        fs::write(&path, "old")?;
+        let ret = write_file_atomic(&path, |w| {
+            w.write_all(b"partial")?;
+            anyhow::bail!("failed")
+        });
+        assert!(ret.is_err());
+        assert_eq!(fs::read_to_string(&path)?, "old");
+        // the old file is still readable while the new one is written
+        write_file_atomic(&path, |w| {
+            let old = fs::read_to_string(&path)?;
+            Ok(write!(w, "{} and new", old)?)
+        })?;
+        assert_eq!(fs::read_to_string(&path)?, "old and new");
+        assert_eq!(fs::read_dir(dir.join(""))?.count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Result;
+
+fn rcli(args: &[&str]) -> Result<String> {
+    rcli_env(args, &[])
+}
+
+fn rcli_env(args: &[&str], envs: &[(&str, &str)]) -> Result<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rcli"))
+        .args(args)
+        .env_remove("RCLI_PASSPHRASE")
+        .env_remove("RCLI_PASSPHRASE_FILE")
+        .envs(envs.iter().copied())
+        .output()?;
+    anyhow::ensure!(
+        output.status.success(),
+        "rcli {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+fn workdir(name: &str) -> Result<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn generate_sign_verify(format: &str, key_format: &str, sk: &str, pk: &str) -> Result<()> {
+    let dir = workdir(&format!("{}-{}", format, key_format))?;
+    let out = dir.to_string_lossy();
+    rcli(&[
+        "text",
+        "generate",
+        "--format",
+        format,
+        "--key-format",
+        key_format,
+        "--output",
+        &out,
+    ])?;
+    sign_verify(&dir, format, &dir.join(sk), &dir.join(pk))
+}
+
+fn sign_verify(dir: &Path, format: &str, sk: &Path, pk: &Path) -> Result<()> {
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    fs::write(path("msg.txt"), "hello world")?;
+    fs::write(path("other.txt"), "hello there")?;
+
+    let sig = rcli(&[
+        "text",
+        "sign",
+        "--format",
+        format,
+        "-k",
+        &sk.to_string_lossy(),
+        "-i",
+        &path("msg.txt"),
+    ])?;
+    let verify = |input: &str| {
+        rcli(&[
+            "text",
+            "verify",
+            "--format",
+            format,
+            "-k",
+            &pk.to_string_lossy(),
+            "-i",
+            &path(input),
+            // a signature may start with '-'
+            &format!("--sig={}", sig),
+        ])
+    };
+    assert_eq!(verify("msg.txt")?, "true");
+    assert_eq!(verify("other.txt")?, "false");
+    Ok(())
+}
+
+#[test]
+fn test_text_blake3() -> Result<()> {
+    generate_sign_verify("blake3", "raw", "blake3.txt", "blake3.txt")
+}
+
+#[test]
+fn test_text_ed25519() -> Result<()> {
+    for key_format in ["raw", "pem", "openssh", "jwk"] {
+        generate_sign_verify("ed25519", key_format, "ed25519.sk", "ed25519.pk")?;
+    }
+    generate_sign_verify("ed25519ph", "raw", "ed25519.sk", "ed25519.pk")
+}
+
+#[test]
+fn test_text_external_keys() -> Result<()> {
+    // written by `ssh-keygen -t ed25519` and `openssl genpkey -algorithm ed25519`
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let keys = [
+        ("ed25519_openssh", "ed25519_openssh.pub"),
+        ("ed25519_pkcs8.pem", "ed25519_pkcs8.pub.pem"),
+    ];
+    for (i, (sk, pk)) in keys.iter().enumerate() {
+        let dir = workdir(&format!("external-{}", i))?;
+        sign_verify(&dir, "ed25519", &assets.join(sk), &assets.join(pk))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_text_format_names() -> Result<()> {
+    // the default format and the old spellings
+    let dir = workdir("default")?;
+    let dir = dir.to_string_lossy();
+    rcli(&["text", "generate", "--output", &dir])?;
+    rcli(&["text", "generate", "--format", "ed2519", "--output", &dir])?;
+    // an existing key is never overwritten
+    assert!(rcli(&["text", "generate", "--format", "blacke3", "--output", &dir]).is_err());
+    let other = workdir("default-other")?;
+    let other = other.to_string_lossy();
+    rcli(&[
+        "text", "generate", "--format", "blacke3", "--output", &other,
+    ])?;
+    assert!(rcli(&["text", "generate", "--format", "rsa"]).is_err());
+    assert!(rcli(&["text", "generate", "--key-format", "pem", "--output", &dir]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_text_encrypted_key() -> Result<()> {
+    let dir = workdir("encrypted")?;
+    let out = dir.to_string_lossy();
+    let sk = dir.join("ed25519.sk").to_string_lossy().into_owned();
+    let msg = dir.join("msg.txt").to_string_lossy().into_owned();
+    fs::write(&msg, "hello world")?;
+    let old = [("RCLI_PASSPHRASE", "old secret")];
+    rcli_env(
+        &[
+            "text",
+            "generate",
+            "--format",
+            "ed25519",
+            "--encrypt",
+            "--output",
+            &out,
+        ],
+        &old,
+    )?;
+    assert!(fs::read_to_string(&sk)?.starts_with("-----BEGIN RCLI ENCRYPTED KEY-----"));
+
+    let sign = |envs: &[(&str, &str)]| {
+        rcli_env(
+            &["text", "sign", "--format", "ed25519", "-k", &sk, "-i", &msg],
+            envs,
+        )
+    };
+    let sig = sign(&old)?;
+    assert!(sign(&[("RCLI_PASSPHRASE", "wrong")]).is_err());
+    // an unreadable passphrase file fails rather than asking on the terminal
+    let missing = dir.join("missing").to_string_lossy().into_owned();
+    assert!(sign(&[("RCLI_PASSPHRASE_FILE", &missing)]).is_err());
+
+    let new = [("RCLI_PASSPHRASE", "new secret")];
+    rcli_env(
+        &["text", "key", "rekey", "-k", &sk],
+        &[old[0], ("RCLI_NEW_PASSPHRASE", "new secret")],
+    )?;
+    assert!(sign(&old).is_err());
+    // Ed25519 signatures are deterministic
+    assert_eq!(sign(&new)?, sig);
+    Ok(())
+}
+
+#[test]
+fn test_text_encrypt_decrypt() -> Result<()> {
+    let dir = workdir("crypt")?;
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let data = (0..200_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    fs::write(path("data.bin"), &data)?;
+    rcli(&["text", "generate", "--output", &path("")])?;
+    let key = path("blake3.txt");
+
+    rcli(&[
+        "text",
+        "encrypt",
+        "-k",
+        &key,
+        "-i",
+        &path("data.bin"),
+        "-o",
+        &path("data.enc"),
+    ])?;
+    rcli(&[
+        "text",
+        "decrypt",
+        "-k",
+        &key,
+        "-i",
+        &path("data.enc"),
+        "-o",
+        &path("data.out"),
+    ])?;
+    assert_eq!(fs::read(path("data.out"))?, data);
+    // the key is required for key encrypted input
+    assert!(rcli(&["text", "decrypt", "-i", &path("data.enc")]).is_err());
+
+    let pass = [("RCLI_PASSPHRASE", "correct horse")];
+    let encrypt = [
+        "text",
+        "encrypt",
+        "-i",
+        &path("data.bin"),
+        "-o",
+        &path("pass.enc"),
+    ];
+    rcli_env(&encrypt, &pass)?;
+    let decrypt = [
+        "text",
+        "decrypt",
+        "-i",
+        &path("pass.enc"),
+        "-o",
+        &path("pass.out"),
+    ];
+    rcli_env(&decrypt, &pass)?;
+    assert_eq!(fs::read(path("pass.out"))?, data);
+
+    fs::remove_file(path("pass.out"))?;
+    assert!(rcli_env(&decrypt, &[("RCLI_PASSPHRASE", "wrong")]).is_err());
+    assert!(!dir.join("pass.out").exists());
+
+    // no partial output is left behind on failure either
+    let missing = path("missing");
+    let encrypt = [
+        "text",
+        "encrypt",
+        "-i",
+        &path("data.bin"),
+        "-o",
+        &path("fail.enc"),
+    ];
+    assert!(rcli_env(&encrypt, &[("RCLI_PASSPHRASE_FILE", &missing)]).is_err());
+    assert!(!dir.join("fail.enc").exists());
+
+    // the input may be the output
+    fs::write(path("inplace"), &data)?;
+    let encrypt = [
+        "text",
+        "encrypt",
+        "-i",
+        &path("inplace"),
+        "-o",
+        &path("inplace"),
+    ];
+    rcli_env(&encrypt, &pass)?;
+    let encrypted = fs::read(path("inplace"))?;
+    assert_ne!(encrypted, data);
+    let decrypt = [
+        "text",
+        "decrypt",
+        "-i",
+        &path("inplace"),
+        "-o",
+        &path("inplace"),
+    ];
+    assert!(rcli_env(&decrypt, &[("RCLI_PASSPHRASE", "wrong")]).is_err());
+    assert_eq!(fs::read(path("inplace"))?, encrypted);
+    rcli_env(&decrypt, &pass)?;
+    assert_eq!(fs::read(path("inplace"))?, data);
+    // and no temp files are left
+    assert!(fs::read_dir(&dir)?.all(|e| !e
+        .unwrap()
+        .file_name()
+        .to_string_lossy()
+        .ends_with(".tmp")));
+    Ok(())
+}